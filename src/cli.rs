@@ -1,5 +1,12 @@
 use clap::ValueHint::{self};
-use clap::{Args, Parser, Subcommand, arg};
+use clap::{
+    Args, Parser, Subcommand, ValueEnum,
+    builder::{EnumValueParser, RangedU64ValueParser, TypedValueParser},
+};
+
+use std::{path::PathBuf, time::Duration};
+
+use crate::{comparer::sprt::SPRTModel, formats::Format, graph::TieBreaker};
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -11,6 +18,8 @@ pub struct Cli {
 pub enum CliCommands {
     /// Compares two optimizers.
     Compare(CompareArgs),
+    /// Plays a round-robin tournament between several optimizers, and rates them
+    Tournament(TournamentArgs),
     /// Runs your solver with a set of graphs
     Graphs {
         #[arg(value_hint=ValueHint::CommandString)]
@@ -23,9 +32,64 @@ pub enum CliCommands {
         skip_to: Option<String>,
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         save: bool,
+        /// Accept outputs with relabeled nodes, if they are isomorphic to the input
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        isomorphism: bool,
+        /// Print the edges with the most crossings, and what they cross
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        explain: bool,
+        /// Seconds the optimizer may spend on a graph
+        #[arg(long, value_parser = parse_seconds)]
+        timeout: Option<f64>,
+        /// Extra seconds after the timeout before the optimizer gets killed and restarted
+        #[arg(long, default_value = "5", value_parser = parse_seconds)]
+        grace: f64,
+        /// Time budget sent to optimizers that accept params. Defaults to the timeout.
+        #[arg(long)]
+        time_limit_ms: Option<u64>,
+        /// Seed sent to optimizers that accept params. Random by default.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// How many optimizers to run in parallel
+        #[arg(short, long, default_value = "1", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: usize,
+        /// Skip graphs that already have a result from this optimizer version
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        resume: bool,
+        /// When resuming, run the graphs where the optimizer crashed or timed out again
+        #[arg(long, action = clap::ArgAction::SetTrue, requires = "resume")]
+        retry_failed: bool,
+        /// Optimize every graph this often, each time with a different seed
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
     },
     /// Generates a plot for the leaderboard
-    Leaderboard {},
+    Leaderboard {
+        /// Metrics for ranking drawings with the same crossing number, in order
+        #[arg(long, value_delimiter = ',', value_parser = tie_breaker_parser())]
+        tie_breaker: Vec<TieBreaker>,
+    },
+    /// Lists the best known drawing of every graph, and how far each team is from it
+    Best {},
+    /// Converts GraphML, GML, DOT or edge list files to JSON graphs in ./graphs
+    Convert {
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        files: Vec<PathBuf>,
+        /// Folder in ./graphs for the converted graphs
+        #[arg(long, default_value = "imported")]
+        into: String,
+        /// Format of the files. Guessed from the file extension by default.
+        #[arg(long)]
+        format: Option<Format>,
+    },
+    /// Draws a graph as an SVG, with the edges colored by their crossings
+    Render {
+        #[arg(value_hint = ValueHint::FilePath)]
+        graph: PathBuf,
+        /// Where to write the SVG. Defaults to the graph path with an .svg extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
     /// Generate evil graphs (WIP)
     Adversary {},
 }
@@ -47,11 +111,20 @@ pub struct CompareArgs {
     #[clap(long, default_value = "2")]
     pub max_games: u32,
 
+    /// Games per instance, or game pairs with the pentanomial SPRT
     #[clap(long, default_value = "1")]
     pub rounds: u32,
 
-    #[clap(long, short)]
-    pub seed: Option<u64>,
+    /// Statistical model of the SPRT. The pentanomial one plays game pairs, and its Elo bounds are normalized Elo.
+    #[clap(long, value_enum, default_value = "trinomial")]
+    pub sprt: SPRTModel,
+
+    #[command(flatten)]
+    pub instances: InstanceArgs,
+
+    /// How many pairs of optimizers play at the same time. The result doesn't depend on it.
+    #[clap(long, default_value = "1", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub concurrency: usize,
 
     #[arg(value_hint=ValueHint::CommandString)]
     pub optimizer1: String,
@@ -59,3 +132,116 @@ pub struct CompareArgs {
     #[arg(value_hint=ValueHint::CommandString)]
     pub optimizer2: String,
 }
+
+#[derive(Debug, Args)]
+pub struct TournamentArgs {
+    /// How many instances every optimizer gets
+    #[clap(long, default_value = "10")]
+    pub max_games: u32,
+
+    /// Only play the first optimizer against the others, instead of everyone against everyone
+    #[clap(long)]
+    pub gauntlet: bool,
+
+    #[command(flatten)]
+    pub instances: InstanceArgs,
+
+    /// Where to save the crosstable
+    #[clap(short, long, default_value = "tournament.csv")]
+    pub output: PathBuf,
+
+    #[arg(required = true, num_args = 2.., value_hint = ValueHint::CommandString)]
+    pub optimizers: Vec<String>,
+}
+
+/// How the instances of a match or tournament are chosen and played
+#[derive(Debug, Args)]
+pub struct InstanceArgs {
+    #[clap(long, short)]
+    pub seed: Option<u64>,
+
+    /// Metrics for deciding games with the same crossing number, in order
+    #[clap(long, value_delimiter = ',', value_parser = tie_breaker_parser())]
+    pub tie_breaker: Vec<TieBreaker>,
+
+    /// Generate a set of allowed node positions, with this many points per node
+    #[clap(long, value_parser = parse_points_per_node)]
+    pub points: Option<f32>,
+
+    /// Use the graphs in ./graphs whose names contain this, instead of random graphs
+    #[clap(long)]
+    pub graphs: Option<String>,
+
+    /// Pick the graphs randomly (with the seed) instead of going through them in order
+    #[clap(long, requires = "graphs")]
+    pub sample: bool,
+
+    /// Time budget sent to optimizers that accept params
+    #[clap(long)]
+    pub time_limit_ms: Option<u64>,
+
+    /// Seconds an optimizer may spend on a graph
+    #[clap(long, value_parser = parse_seconds)]
+    pub timeout: Option<f64>,
+
+    /// Extra seconds after the timeout before an optimizer gets killed and restarted
+    #[clap(long, default_value = "5", value_parser = parse_seconds)]
+    pub grace: f64,
+}
+
+impl InstanceArgs {
+    /// When to kill an optimizer, including the grace period
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+            .map(|v| Duration::from_secs_f64(v).saturating_add(Duration::from_secs_f64(self.grace)))
+    }
+}
+
+/// The command line names of the [`TieBreaker`]s
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TieBreakerArg {
+    /// Fewer crossings in total
+    Total,
+    /// Fewer edges with the maximum number of crossings
+    EdgesAtMax,
+    /// A larger minimum crossing angle
+    CrossingAngle,
+    /// More uniform edge lengths
+    EdgeLengthVariance,
+    /// Using more of the drawing area
+    BoundingBox,
+}
+
+impl From<TieBreakerArg> for TieBreaker {
+    fn from(value: TieBreakerArg) -> Self {
+        match value {
+            TieBreakerArg::Total => TieBreaker::Total,
+            TieBreakerArg::EdgesAtMax => TieBreaker::EdgesAtMax,
+            TieBreakerArg::CrossingAngle => TieBreaker::CrossingAngle,
+            TieBreakerArg::EdgeLengthVariance => TieBreaker::EdgeLengthVariance,
+            TieBreakerArg::BoundingBox => TieBreaker::BoundingBox,
+        }
+    }
+}
+
+fn tie_breaker_parser() -> impl TypedValueParser<Value = TieBreaker> {
+    EnumValueParser::<TieBreakerArg>::new().map(TieBreaker::from)
+}
+
+/// Every node needs its own point, so there must be at least one point per node
+fn parse_points_per_node(value: &str) -> Result<f32, String> {
+    let points: f32 = value.parse().map_err(|e| format!("{e}"))?;
+    if points >= 1.0 {
+        Ok(points)
+    } else {
+        Err(format!("must be at least 1, but is {value}"))
+    }
+}
+
+/// A number of seconds that fits into a [`Duration`]
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    let seconds: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("must be a finite, non-negative number of seconds, but is {value}"))?;
+    Ok(seconds)
+}
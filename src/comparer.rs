@@ -1,2 +1,3 @@
 pub mod compare_mode;
 pub mod sprt;
+pub mod tournament;
@@ -1,133 +1,720 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use petgraph::graph::UnGraph;
-use petgraph_gen::random_gnp_graph;
-use rand::{Rng, SeedableRng};
-use smol::{future, io};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use smol::{
+    LocalExecutor,
+    channel::{self, Receiver, Sender},
+    future, io,
+};
 
 use crate::{
     cli::CompareArgs,
-    comparer::sprt::{self, SPRT, elo_wld},
-    graph::{Edge, Graph, Node},
-    optimizer_protocol::{AllOk, Optimizer},
+    comparer::sprt::{self, PentanomialSPRT, SPRT, SPRTModel, elo_wld, nelo_pentanomial},
+    generator::random_gnp_instance,
+    graph::{Graph, QualityMetrics, TieBreaker},
+    graphs_runner::{collect_graphs, drawing_metrics, filter_graphs, score_in_background},
+    leaderboard::stats::Outcome,
+    optimizer_protocol::{AllOk, GraphParams, Optimizer, OptimizerResponse},
 };
 
 pub fn compare_mode(cli: CompareArgs) -> impl Future<Output = io::Result<()>> {
-    let sprt = SPRT::new(cli.elo0, cli.elo1, cli.alpha, cli.beta);
-
-    let mut wins = 0;
-    let mut draws = 0;
-    let mut losses = 0;
+    let timeout = cli.instances.timeout();
 
     let mut thread_rng = rand::thread_rng();
-    let seed = match cli.seed {
+    let seed = match cli.instances.seed {
         Some(seed) => seed,
         None => thread_rng.r#gen(),
     };
 
-    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed);
-
-    let mut optimizer1 = Optimizer::new(&cli.optimizer1, 1);
-    let mut optimizer2 = Optimizer::new(&cli.optimizer2, 2);
+    let mut rng = SmallRng::seed_from_u64(seed);
 
-    let redirect_stderr = future::zip(optimizer1.redirect_stderr(), optimizer2.redirect_stderr());
+    let corpus = load_corpus(cli.instances.graphs.as_deref());
+    let concurrency = cli.concurrency;
 
     println!("seed: {seed}");
 
-    let run_optimizers = async move {
-        let (name1, name2) = future::zip(optimizer1.read_start(), optimizer2.read_start()).await;
-        let (name1, name2) = (name1?, name2?);
+    async move {
+        let mut matches: Vec<(Contestant, Contestant)> = vec![];
+        for worker_id in 0..concurrency as u32 {
+            let mut contestant1 =
+                Contestant::new(Optimizer::new(&cli.optimizer1, 2 * worker_id + 1));
+            let mut contestant2 =
+                Contestant::new(Optimizer::new(&cli.optimizer2, 2 * worker_id + 2));
+            smol::spawn(contestant1.optimizer.redirect_stderr()).detach();
+            smol::spawn(contestant2.optimizer.redirect_stderr()).detach();
 
-        let mut current_instance = 0;
-        while current_instance < cli.max_games {
-            let nodes = rng.gen_range(10..200);
-            let probability = rng.gen_range(0.1..0.8);
+            let (info1, info2) = future::zip(
+                contestant1.optimizer.read_start(),
+                contestant2.optimizer.read_start(),
+            )
+            .await;
+            contestant1.name = info1?.label();
+            contestant2.name = info2?.label();
 
-            let graph: UnGraph<_, _, usize> = random_gnp_graph(&mut rng, nodes, probability);
-            println!(
-                "[{current_instance}] #node: {}, #edge: {}",
-                graph.node_count(),
-                graph.edge_count()
-            );
+            // All pairs run the same commands, so their failures are counted together
+            if let Some((first1, first2)) = matches.first() {
+                for (first, contestant) in [(first1, &contestant1), (first2, &contestant2)] {
+                    if first.name != contestant.name {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "optimizer {} started as {:?}, but optimizer {} as {:?}",
+                                contestant.optimizer.id(),
+                                contestant.name,
+                                first.optimizer.id(),
+                                first.name
+                            ),
+                        ));
+                    }
+                }
+            }
+            matches.push((contestant1, contestant2));
+        }
+        let names = [matches[0].0.name.clone(), matches[0].1.name.clone()];
+        if concurrency > 1 {
+            println!("Running {concurrency} pairs of optimizers in parallel");
+        }
 
-            let graph = Arc::new(Graph {
-                nodes: (0..graph.node_count())
-                    .into_iter()
-                    .map(|id| Node {
-                        id,
-                        x: rng.gen_range(0..1000),
-                        y: rng.gen_range(0..1000),
-                    })
-                    .collect(),
-                points: vec![],
-                edges: graph
-                    .raw_edges()
-                    .into_iter()
-                    .map(|e| Edge {
-                        source: e.source().index(),
-                        target: e.target().index(),
-                    })
-                    .collect(),
-                width: 1_000_000,
-                height: 1_000_000,
-            });
-
-            println!(
-                "Started instance {} of {} ({:?} vs {:?})",
-                current_instance, cli.max_games, name1, name2
-            );
+        let settings = MatchSettings {
+            rounds: cli.rounds,
+            model: cli.sprt,
+            seed,
+            time_limit_ms: cli.instances.time_limit_ms,
+            timeout,
+            tie_breaker: cli.instances.tie_breaker.clone(),
+            concurrency,
+        };
+        let mut tally = Tally::new(&cli, names);
 
-            _ = future::zip(
-                optimizer1.write_graph(&graph),
-                optimizer2.write_graph(&graph),
-            )
+        // The instances are generated in order, so they only depend on the seed
+        let (instance_sender, instances) = channel::bounded(concurrency);
+        let (result_sender, results) = channel::unbounded();
+        let executor = LocalExecutor::new();
+        let generator = executor.spawn(async move {
+            for current_instance in 0..cli.max_games {
+                let graph = next_instance(
+                    &corpus,
+                    cli.instances.sample,
+                    cli.instances.points,
+                    &mut rng,
+                    current_instance,
+                )?;
+                if instance_sender
+                    .send((current_instance, graph))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            io::Result::Ok(())
+        });
+        let workers = matches
+            .into_iter()
+            .map(|(contestant1, contestant2)| {
+                let (instances, result_sender) = (instances.clone(), result_sender.clone());
+                let settings = &settings;
+                executor.spawn(async move {
+                    let played = play_instances(
+                        contestant1,
+                        contestant2,
+                        instances,
+                        result_sender.clone(),
+                        settings,
+                    )
+                    .await;
+                    // Ends the match right away, instead of playing on with the other pairs
+                    if let Err(e) = played {
+                        _ = result_sender.send(Err(e)).await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        std::mem::drop((instances, result_sender));
+
+        executor
+            .run(async {
+                if merge_results(&results, &mut tally).await?.is_some() {
+                    // Games that are still running don't matter anymore
+                    return Ok(());
+                }
+
+                generator.await?;
+                for worker in workers {
+                    worker.await;
+                }
+                Ok(())
+            })
             .await
-            .all_ok()?;
+    }
+}
+
+/// How the games of each instance are played
+struct MatchSettings {
+    rounds: u32,
+    model: SPRTModel,
+    seed: u64,
+    time_limit_ms: Option<u64>,
+    timeout: Option<Duration>,
+    tie_breaker: Vec<TieBreaker>,
+    concurrency: usize,
+}
+
+impl MatchSettings {
+    fn games_per_instance(&self) -> u64 {
+        match self.model {
+            SPRTModel::Trinomial => self.rounds as u64,
+            SPRTModel::Pentanomial => 2 * self.rounds as u64,
+        }
+    }
+}
+
+/// The games of one instance, in the order they were played
+struct InstanceResult {
+    games: Vec<std::cmp::Ordering>,
+    failures: [BTreeMap<Outcome, u32>; 2],
+}
+
+/// The score of the match so far
+struct Tally {
+    names: [String; 2],
+    model: SPRTModel,
+    sprt: SPRT,
+    pentanomial: PentanomialSPRT,
+    max_games: u32,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    /// Game pairs by the score of the first optimizer, from 0 to 2 in half points
+    pairs: [u32; 5],
+    failures: [BTreeMap<Outcome, u32>; 2],
+}
+
+impl Tally {
+    fn new(cli: &CompareArgs, names: [String; 2]) -> Self {
+        Self {
+            names,
+            model: cli.sprt,
+            sprt: SPRT::new(cli.elo0, cli.elo1, cli.alpha, cli.beta),
+            pentanomial: PentanomialSPRT::new(cli.elo0, cli.elo1, cli.alpha, cli.beta),
+            max_games: cli.max_games,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            pairs: [0; 5],
+            failures: [BTreeMap::new(), BTreeMap::new()],
+        }
+    }
 
-            let (graph1, graph2) =
-                future::zip(optimizer1.read_graph(), optimizer2.read_graph()).await;
-            let (graph1, graph2) = (graph1?, graph2?);
+    /// Counts the games of an instance and prints the score.
+    /// Returns whether the SPRT has decided.
+    fn add(&mut self, current_instance: u32, result: InstanceResult) -> bool {
+        for (total, instance_failures) in self.failures.iter_mut().zip(result.failures) {
+            for (outcome, count) in instance_failures {
+                *total.entry(outcome).or_default() += count;
+            }
+        }
 
-            let crossings1 = graph1.crossings().max_per_edge;
-            let crossings2 = graph2.crossings().max_per_edge;
-            println!("{} max edge crossing: {}", name1, crossings1);
-            println!("{} max edge crossing: {}", name2, crossings2);
+        let games_per_round = match self.model {
+            SPRTModel::Trinomial => 1,
+            SPRTModel::Pentanomial => 2,
+        };
+        for round in result.games.chunks(games_per_round) {
+            let mut pair_score = 0;
+            for ordering in round {
+                match ordering {
+                    std::cmp::Ordering::Less => {
+                        self.wins += 1;
+                        pair_score += 2;
+                    }
+                    std::cmp::Ordering::Equal => {
+                        self.draws += 1;
+                        pair_score += 1;
+                    }
+                    std::cmp::Ordering::Greater => self.losses += 1,
+                }
+            }
+            if self.model == SPRTModel::Pentanomial {
+                self.pairs[pair_score] += 1;
+            }
+        }
 
-            match crossings1.cmp(&crossings2) {
-                std::cmp::Ordering::Less => wins += 1,
-                std::cmp::Ordering::Equal => draws += 1,
-                std::cmp::Ordering::Greater => losses += 1,
+        println!("Finished instance {current_instance} of {}", self.max_games);
+        for (name, failures) in self.names.iter().zip(&self.failures) {
+            if !failures.is_empty() {
+                println!("{name} failures: {}", format_failures(failures));
             }
+        }
 
-            let (e1, e2, e3) = elo_wld(wins, losses, draws);
-            println!("ELO: {e2:.3} +- {:.3} [{e1:.3}, {e3:.3}]", (e3 - e1) / 2.0);
+        let (wins, draws, losses) = (self.wins, self.draws, self.losses);
+        let (status, bounds) = match self.model {
+            SPRTModel::Trinomial => {
+                let (e1, e2, e3) = elo_wld(wins, losses, draws);
+                println!("ELO: {e2:.3} +- {:.3} [{e1:.3}, {e3:.3}]", (e3 - e1) / 2.0);
 
-            let status = sprt.status(wins, losses, draws);
-            match status.result {
-                sprt::SPRTResult::AcceptH0 => break,
-                sprt::SPRTResult::AcceptH1 => break,
-                sprt::SPRTResult::Continue => (),
+                let sprt = &self.sprt;
+                let status = sprt.status(wins, losses, draws);
+                (
+                    status,
+                    (sprt.elo0(), sprt.elo1(), sprt.lower(), sprt.upper()),
+                )
             }
+            SPRTModel::Pentanomial => {
+                let pairs = self.pairs;
+                let (e1, e2, e3) = nelo_pentanomial(&pairs);
+                println!("nELO: {e2:.3} +- {:.3} [{e1:.3}, {e3:.3}]", (e3 - e1) / 2.0);
+                println!("Pairs (0-2): {pairs:?}, W/D/L: {wins}/{draws}/{losses}");
 
-            println!(
-                "LLR: {:.3} [{}, {}] ({:.3}, {:.3})",
-                status.llr,
-                sprt.elo0(),
-                sprt.elo1(),
-                sprt.lower(),
-                sprt.upper()
-            );
+                let pentanomial = &self.pentanomial;
+                let status = pentanomial.status(&pairs);
+                let bounds = (
+                    pentanomial.nelo0(),
+                    pentanomial.nelo1(),
+                    pentanomial.lower(),
+                    pentanomial.upper(),
+                );
+                (status, bounds)
+            }
+        };
+        match status.result {
+            sprt::SPRTResult::AcceptH0 | sprt::SPRTResult::AcceptH1 => return true,
+            sprt::SPRTResult::Continue => (),
+        }
+
+        let (elo0, elo1, lower, upper) = bounds;
+        println!(
+            "LLR: {:.3} [{}, {}] ({:.3}, {:.3})",
+            status.llr, elo0, elo1, lower, upper
+        );
+        false
+    }
+}
 
+/// Counts the results in instance order, no matter which worker finishes first.
+/// Returns the instance that decided the SPRT, if any, or the first error of a worker.
+async fn merge_results(
+    results: &Receiver<io::Result<(u32, InstanceResult)>>,
+    tally: &mut Tally,
+) -> io::Result<Option<u32>> {
+    let mut pending = BTreeMap::new();
+    let mut current_instance = 0;
+    while let Ok(received) = results.recv().await {
+        let (index, result) = received?;
+        pending.insert(index, result);
+        while let Some(result) = pending.remove(&current_instance) {
+            if tally.add(current_instance, result) {
+                return Ok(Some(current_instance));
+            }
             current_instance += 1;
         }
-        io::Result::Ok(())
+    }
+    Ok(None)
+}
+
+/// Plays instances from the queue until it is empty
+async fn play_instances(
+    mut contestant1: Contestant,
+    mut contestant2: Contestant,
+    instances: Receiver<(u32, Graph)>,
+    results: Sender<io::Result<(u32, InstanceResult)>>,
+    settings: &MatchSettings,
+) -> io::Result<()> {
+    while let Ok((current_instance, graph)) = instances.recv().await {
+        let worker = if settings.concurrency > 1 {
+            format!(
+                " [Optimizers {} and {}]",
+                contestant1.optimizer.id(),
+                contestant2.optimizer.id()
+            )
+        } else {
+            String::new()
+        };
+        println!(
+            "Started instance {} ({:?} vs {:?}){worker}",
+            current_instance, contestant1.name, contestant2.name
+        );
+
+        // The second game of a pair starts from the mirrored layout
+        let mirrored = match settings.model {
+            SPRTModel::Trinomial => None,
+            SPRTModel::Pentanomial => Some(mirrored_layout(&graph)),
+        };
+
+        // Every game has its own seed, which only depends on its position in the match
+        let mut current_game = current_instance as u64 * settings.games_per_instance();
+        let mut games = vec![];
+        for _ in 0..settings.rounds {
+            for graph in std::iter::once(&graph).chain(mirrored.as_ref()) {
+                // Both optimizers get the same seed, without changing the generated instances
+                let params = GraphParams {
+                    time_limit_ms: settings.time_limit_ms,
+                    seed: Some(settings.seed.wrapping_add(current_game)),
+                };
+                current_game += 1;
+
+                let ordering = play_game(
+                    &mut contestant1,
+                    &mut contestant2,
+                    graph,
+                    &params,
+                    settings.timeout,
+                    &settings.tie_breaker,
+                )
+                .await?;
+                games.push(ordering);
+            }
+        }
+
+        let result = InstanceResult {
+            games,
+            failures: [
+                std::mem::take(&mut contestant1.failures),
+                std::mem::take(&mut contestant2.failures),
+            ],
+        };
+        if results.send(Ok((current_instance, result))).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Graphs from ./graphs whose names contain the filter, instead of random ones
+pub fn load_corpus(filter: Option<&str>) -> Option<Vec<(PathBuf, String)>> {
+    filter.map(|filter| {
+        let graphs = collect_graphs(Path::new("./graphs"))
+            .map(|g| filter_graphs(g, Some(filter)))
+            .expect("./graphs folder should exist and be full of graphs");
+        if graphs.is_empty() {
+            panic!("No graphs in the ./graphs folder match {filter:?}");
+        }
+        graphs
+    })
+}
+
+/// Reads the next graph of the corpus, or generates a random one
+pub fn next_instance(
+    corpus: &Option<Vec<(PathBuf, String)>>,
+    sample: bool,
+    points: Option<f32>,
+    rng: &mut SmallRng,
+    current_instance: u32,
+) -> io::Result<Graph> {
+    let graph = match corpus {
+        Some(graphs) => {
+            let index = if sample {
+                rng.gen_range(0..graphs.len())
+            } else {
+                current_instance as usize % graphs.len()
+            };
+            let (path, name) = &graphs[index];
+            println!("[{current_instance}] {name}");
+            serde_json::from_slice::<Graph>(&std::fs::read(path)?)?
+        }
+        None => random_gnp_instance(rng, points),
     };
+    println!(
+        "[{current_instance}] #node: {}, #edge: {}, #point: {}",
+        graph.nodes.len(),
+        graph.edges.len(),
+        graph.points.len(),
+    );
+    Ok(graph)
+}
+
+/// An optimizer, with the failures it had so far
+pub struct Contestant {
+    pub optimizer: Optimizer,
+    pub name: String,
+    pub failures: BTreeMap<Outcome, u32>,
+}
+
+impl Contestant {
+    pub fn new(optimizer: Optimizer) -> Self {
+        Self {
+            optimizer,
+            name: String::new(),
+            failures: BTreeMap::new(),
+        }
+    }
+}
+
+/// Plays one game, and returns [`std::cmp::Ordering::Less`] when the first optimizer wins
+async fn play_game(
+    contestant1: &mut Contestant,
+    contestant2: &mut Contestant,
+    graph: &Graph,
+    params: &GraphParams,
+    timeout: Option<Duration>,
+    tie_breaker: &[TieBreaker],
+) -> io::Result<std::cmp::Ordering> {
+    let (result1, result2) = future::zip(
+        play(&mut contestant1.optimizer, graph, params, timeout),
+        play(&mut contestant2.optimizer, graph, params, timeout),
+    )
+    .await
+    .all_ok()?;
+    let metrics1 = score_result(&contestant1.name, result1, &mut contestant1.failures);
+    let metrics2 = score_result(&contestant2.name, result2, &mut contestant2.failures);
+
+    Ok(game_ordering(
+        metrics1.as_ref(),
+        metrics2.as_ref(),
+        tie_breaker,
+    ))
+}
+
+/// [`std::cmp::Ordering::Less`] when the first drawing wins. Failures lose against everything.
+pub fn game_ordering(
+    metrics1: Option<&QualityMetrics>,
+    metrics2: Option<&QualityMetrics>,
+    tie_breaker: &[TieBreaker],
+) -> std::cmp::Ordering {
+    match (metrics1, metrics2) {
+        (Some(metrics1), Some(metrics2)) => metrics1.compare(metrics2, tie_breaker),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// The same graph, with the node positions in reverse order
+fn mirrored_layout(graph: &Graph) -> Graph {
+    let mut mirrored = graph.clone();
+    for (node, original) in mirrored.nodes.iter_mut().zip(graph.nodes.iter().rev()) {
+        node.x = original.x;
+        node.y = original.y;
+    }
+    mirrored
+}
+
+/// Sends the graph to an optimizer and scores the drawing.
+/// Restarts optimizers that crash or don't respond in time.
+pub async fn play(
+    optimizer: &mut Optimizer,
+    graph: &Graph,
+    params: &GraphParams,
+    timeout: Option<Duration>,
+) -> io::Result<Result<QualityMetrics, (Outcome, String)>> {
+    optimizer.read_graph_request().await?;
+    optimizer.write_params(params).await?;
+    optimizer.write_graph(graph).await?;
+
+    let failure = match optimizer.read_response_timeout(timeout).await? {
+        Some(OptimizerResponse::Graph { graph: drawing, .. }) => {
+            let (_, scored) = score_in_background(graph.clone(), drawing, |graph, drawing| {
+                drawing_metrics(graph, drawing, false)
+                    .map(|scored| scored.metrics)
+                    .map_err(|rejection| (rejection.outcome(), rejection.reason()))
+            })
+            .await;
+            return Ok(scored);
+        }
+        Some(OptimizerResponse::Malformed { error, .. }) => {
+            return Ok(Err((Outcome::Malformed, error)));
+        }
+        Some(OptimizerResponse::NoResponse(exit_status)) => (
+            Outcome::Crashed,
+            exit_status.map(|v| v.to_string()).unwrap_or_default(),
+        ),
+        None => (Outcome::Timeout, "no response in time".to_string()),
+        Some(response) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected graph, but got {:?}", response),
+            ));
+        }
+    };
+    optimizer.relaunch().await?;
+    Ok(Err(failure))
+}
+
+/// Prints the crossings or the failure, and counts the failure
+pub fn score_result(
+    name: &str,
+    result: Result<QualityMetrics, (Outcome, String)>,
+    failures: &mut BTreeMap<Outcome, u32>,
+) -> Option<QualityMetrics> {
+    match result {
+        Ok(metrics) => {
+            println!("{} max edge crossing: {}", name, metrics.max_per_edge);
+            Some(metrics)
+        }
+        Err((outcome, reason)) => {
+            println!("{} failed ({}): {}", name, outcome.as_str(), reason);
+            *failures.entry(outcome).or_default() += 1;
+            None
+        }
+    }
+}
+
+pub fn format_failures(failures: &BTreeMap<Outcome, u32>) -> String {
+    failures
+        .iter()
+        .map(|(outcome, count)| format!("{count} {}", outcome.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::{Cli, CliCommands};
+
+    fn tally(args: &[&str]) -> Tally {
+        let args = ["gda_testing", "compare"]
+            .iter()
+            .chain(args)
+            .chain(&["a", "b"]);
+        let CliCommands::Compare(cli) = Cli::parse_from(args).command else {
+            unreachable!()
+        };
+        Tally::new(&cli, ["a".to_string(), "b".to_string()])
+    }
+
+    /// The first optimizer loses a pair and draws one, then sweeps
+    fn instance_result(index: u32) -> InstanceResult {
+        let games = match index {
+            0 => vec![Ordering::Greater, Ordering::Greater],
+            1 => vec![Ordering::Less, Ordering::Greater],
+            _ => vec![Ordering::Less, Ordering::Less],
+        };
+        let mut failures = [BTreeMap::new(), BTreeMap::new()];
+        failures[index as usize % 2].insert(Outcome::Timeout, 1);
+        InstanceResult { games, failures }
+    }
+
+    fn merge(order: impl IntoIterator<Item = u32>) -> (Option<u32>, Tally) {
+        let mut tally = tally(&["--sprt", "pentanomial", "--elo1", "30", "--max-games", "30"]);
+        let (sender, results) = channel::unbounded();
+        for index in order {
+            sender
+                .try_send(Ok((index, instance_result(index))))
+                .unwrap();
+        }
+        std::mem::drop(sender);
+        let stop = smol::block_on(merge_results(&results, &mut tally)).unwrap();
+        (stop, tally)
+    }
+
+    #[test]
+    fn merge_out_of_order() {
+        let (stop, in_order) = merge(0..30);
+        let stop = stop.expect("the sweep should decide the SPRT");
+        assert!(stop > 2 && stop < 29);
+        let games = 2 * (stop + 1);
+        assert_eq!(in_order.wins + in_order.draws + in_order.losses, games);
+        assert_eq!(in_order.losses, 3);
+        assert_eq!(in_order.pairs[0], 1);
+        assert_eq!(in_order.pairs[2], 1);
+        assert_eq!(in_order.pairs[4], stop - 1);
+
+        let orders: [Vec<u32>; 2] = [(0..30).rev().collect(), (0..30).map(|i| i ^ 1).collect()];
+        for order in orders {
+            let (other_stop, tally) = merge(order);
+            assert_eq!(other_stop, Some(stop));
+            assert_eq!(
+                (tally.wins, tally.draws, tally.losses),
+                (in_order.wins, in_order.draws, in_order.losses)
+            );
+            assert_eq!(tally.pairs, in_order.pairs);
+            assert_eq!(tally.failures, in_order.failures);
+        }
+    }
+
+    #[test]
+    fn merge_without_decision() {
+        let mut tally = tally(&["--max-games", "3"]);
+        let (sender, results) = channel::unbounded();
+        for index in [2, 0, 1] {
+            let result = InstanceResult {
+                games: vec![Ordering::Equal],
+                failures: [BTreeMap::new(), BTreeMap::new()],
+            };
+            sender.try_send(Ok((index, result))).unwrap();
+        }
+        std::mem::drop(sender);
+        let stop = smol::block_on(merge_results(&results, &mut tally)).unwrap();
+        assert_eq!(stop, None);
+        assert_eq!((tally.wins, tally.draws, tally.losses), (0, 3, 0));
+    }
+
+    #[test]
+    fn merge_stops_at_the_first_error() {
+        let mut tally = tally(&["--max-games", "30"]);
+        let (sender, results) = channel::unbounded();
+        sender.try_send(Ok((0, instance_result(0)))).unwrap();
+        sender
+            .try_send(Err(io::Error::other("optimizer went away")))
+            .unwrap();
+        // The other pairs are still playing, so the channel stays open
+        let error = smol::block_on(merge_results(&results, &mut tally)).unwrap_err();
+        assert_eq!(error.to_string(), "optimizer went away");
+        assert_eq!(tally.losses, 2);
+        std::mem::drop(sender);
+    }
+
+    /// The edge counts of the instances, which tell the graphs of the corpus apart
+    fn instance_edges(
+        corpus: &Option<Vec<(PathBuf, String)>>,
+        sample: bool,
+        rng: &mut SmallRng,
+    ) -> Vec<usize> {
+        (0..10)
+            .map(|i| {
+                next_instance(corpus, sample, None, rng, i)
+                    .unwrap()
+                    .edges
+                    .len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn corpus_instances() {
+        assert!(load_corpus(None).is_none());
+        let corpus = load_corpus(Some("circulant_graph/10"));
+        let graphs = corpus.as_ref().unwrap();
+        assert!(
+            graphs
+                .iter()
+                .all(|(_, name)| name.contains("circulant_graph/10"))
+        );
+        let edges = graphs
+            .iter()
+            .map(|(path, _)| {
+                let graph: Graph = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+                graph.edges.len()
+            })
+            .collect::<Vec<_>>();
+
+        // Without --sample, the instances go through the corpus in order
+        let mut rng = SmallRng::seed_from_u64(1);
+        let in_order = instance_edges(&corpus, false, &mut rng);
+        let expected = (0..10).map(|i| edges[i % edges.len()]).collect::<Vec<_>>();
+        assert_eq!(in_order, expected);
+
+        // With it, the seed decides
+        let sampled = instance_edges(&corpus, true, &mut SmallRng::seed_from_u64(1));
+        assert_eq!(
+            sampled,
+            instance_edges(&corpus, true, &mut SmallRng::seed_from_u64(1))
+        );
+        assert_ne!(sampled, in_order);
+        assert!(sampled.iter().all(|v| edges.contains(v)));
+    }
 
-    async {
-        let (a, (b, c)) = future::zip(run_optimizers, redirect_stderr).await;
-        a?;
-        b?;
-        c?;
-        Ok(())
+    #[test]
+    #[should_panic(expected = "No graphs in the ./graphs folder match")]
+    fn corpus_without_matches() {
+        load_corpus(Some("no graph is called like this"));
     }
 }
@@ -1,5 +1,7 @@
 use core::f32;
 
+use clap::ValueEnum;
+
 #[derive(Debug, Clone)]
 pub struct SPRT {
     elo0: u32,
@@ -82,6 +84,116 @@ impl SPRT {
     }
 }
 
+/// Which statistical model the SPRT uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SPRTModel {
+    /// Single games, with win/draw/loss counts and BayesElo bounds
+    Trinomial,
+    /// Game pairs, with five possible pair scores and normalized Elo bounds
+    Pentanomial,
+}
+
+/// A SPRT on game pairs. Pairs of games on the same instance are correlated,
+/// which the pentanomial model takes into account, so it needs fewer games than [`SPRT`].
+/// The bounds are in normalized Elo, like on Fishtest.
+#[derive(Debug, Clone)]
+pub struct PentanomialSPRT {
+    nelo0: u32,
+    nelo1: u32,
+    upper: f32,
+    lower: f32,
+}
+
+impl PentanomialSPRT {
+    pub fn new(nelo0: u32, nelo1: u32, alpha: f32, beta: f32) -> Self {
+        Self {
+            nelo0,
+            nelo1,
+            lower: f32::ln(beta / (1.0 - alpha)),
+            upper: f32::ln((1.0 - beta) / alpha),
+        }
+    }
+
+    /// `pairs[i]` counts the pairs where the first optimizer scored `i / 4`,
+    /// so from two losses to two wins
+    pub fn status(&self, pairs: &[u32; 5]) -> SPRTStatus {
+        let llr = pentanomial_llr(pairs, self.nelo0 as f32, self.nelo1 as f32);
+
+        let result = if llr >= self.upper {
+            SPRTResult::AcceptH1
+        } else if llr <= self.lower {
+            SPRTResult::AcceptH0
+        } else {
+            SPRTResult::Continue
+        };
+
+        SPRTStatus { result, llr }
+    }
+
+    pub fn nelo0(&self) -> u32 {
+        self.nelo0
+    }
+
+    pub fn nelo1(&self) -> u32 {
+        self.nelo1
+    }
+
+    pub fn upper(&self) -> f32 {
+        self.upper
+    }
+
+    pub fn lower(&self) -> f32 {
+        self.lower
+    }
+}
+
+/// Normalized Elo difference per (score - 0.5) / standard deviation
+const NELO_DIVIDED_BY_NT: f32 = 800.0 / f32::consts::LN_10;
+
+/// Pseudo count for empty pair scores, so that sweeps still have a variance
+const PAIR_PRIOR: f32 = 1e-3;
+
+/// Mean and variance of the pair scores, and the number of pairs.
+/// Like Fishtest, empty bins count as [`PAIR_PRIOR`] pairs.
+fn pair_statistics(pairs: &[u32; 5]) -> (f32, f32, f32) {
+    let pairs = pairs.map(|n| if n == 0 { PAIR_PRIOR } else { n as f32 });
+    let count = pairs.iter().sum::<f32>();
+    let score = |i: usize| i as f32 / 4.0;
+    let mean = (0..5).map(|i| pairs[i] * score(i)).sum::<f32>() / count;
+    let variance = (0..5)
+        .map(|i| pairs[i] * f32::powi(score(i) - mean, 2))
+        .sum::<f32>()
+        / count;
+    (mean, variance, count)
+}
+
+/// The generalized SPRT approximation from Fishtest
+fn pentanomial_llr(pairs: &[u32; 5], nelo0: f32, nelo1: f32) -> f32 {
+    let (mean, variance, count) = pair_statistics(pairs);
+
+    // Standard deviation of a single game
+    let sigma = f32::sqrt(2.0 * variance);
+    let score0 = 0.5 + nelo0 / NELO_DIVIDED_BY_NT * sigma;
+    let score1 = 0.5 + nelo1 / NELO_DIVIDED_BY_NT * sigma;
+
+    count * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+}
+
+/// Normalized Elo with a 95% confidence interval, like [`elo_wld`]
+pub fn nelo_pentanomial(pairs: &[u32; 5]) -> (f32, f32, f32) {
+    let (mean, variance, count) = pair_statistics(pairs);
+
+    let sigma = f32::sqrt(2.0 * variance);
+    let nelo = |score: f32| (score - 0.5) / sigma * NELO_DIVIDED_BY_NT;
+    let stdev = f32::sqrt(variance / count);
+
+    (
+        nelo(mean + phi_inv(0.025) * stdev),
+        nelo(mean),
+        nelo(mean + phi_inv(0.975) * stdev),
+    )
+}
+
 struct Probability {
     win: f32,
     loss: f32,
@@ -192,3 +304,52 @@ pub fn elo_wld(wins: u32, losses: u32, draws: u32) -> (f32, f32, f32) {
 
     (elo(mu_min), elo(mu), elo(mu_max))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn pentanomial_statistics() {
+        let (mean, variance, count) = pair_statistics(&[1, 2, 3, 4, 5]);
+        assert_close(mean, 2.0 / 3.0, 1e-6);
+        assert_close(variance, 0.097_222, 1e-5);
+        assert_close(count, 15.0, 1e-6);
+
+        assert_close(pentanomial_llr(&[1, 2, 3, 4, 5], 0.0, 5.0), 0.1601, 1e-3);
+
+        let (lower, nelo, upper) = nelo_pentanomial(&[1, 2, 3, 4, 5]);
+        assert_close(nelo, 131.32, 0.1);
+        assert_close(lower, 6.99, 1.0);
+        assert_close(upper, 255.64, 1.0);
+    }
+
+    #[test]
+    fn pentanomial_sweep() {
+        // Without the prior, a sweep has no variance and the test would never end
+        let llr = pentanomial_llr(&[0, 0, 0, 0, 10], 0.0, 5.0);
+        assert_close(llr, 7.4315, 1e-2);
+        let sprt = PentanomialSPRT::new(0, 5, 0.05, 0.05);
+        assert!(matches!(
+            sprt.status(&[0, 0, 0, 0, 10]).result,
+            SPRTResult::AcceptH1
+        ));
+        assert!(matches!(
+            sprt.status(&[10, 0, 0, 0, 0]).result,
+            SPRTResult::AcceptH0
+        ));
+
+        let (lower, nelo, upper) = nelo_pentanomial(&[0, 0, 0, 0, 10]);
+        assert!(lower > 0.0 && lower < nelo && nelo < upper && upper.is_finite());
+
+        // Only draws
+        assert_close(nelo_pentanomial(&[0, 0, 10, 0, 0]).1, 0.0, 1e-2);
+    }
+}
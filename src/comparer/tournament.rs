@@ -0,0 +1,375 @@
+use std::path::Path;
+
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use smol::{LocalExecutor, io};
+
+use crate::{
+    cli::TournamentArgs,
+    comparer::compare_mode::{
+        Contestant, format_failures, game_ordering, load_corpus, next_instance, play, score_result,
+    },
+    optimizer_protocol::{GraphParams, Optimizer},
+};
+
+/// Wins, draws and losses of one optimizer against another
+#[derive(Debug, Clone, Copy, Default)]
+struct Score {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Score {
+    fn add(&mut self, other: Score) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn points(&self) -> f32 {
+        self.wins as f32 + self.draws as f32 / 2.0
+    }
+}
+
+/// A row of the crosstable
+struct Standing {
+    index: usize,
+    total: Score,
+    elo: (f32, f32, f32),
+}
+
+pub async fn tournament(cli: TournamentArgs) -> io::Result<()> {
+    let timeout = cli.instances.timeout();
+
+    let seed = match cli.instances.seed {
+        Some(seed) => seed,
+        None => rand::thread_rng().r#gen(),
+    };
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let corpus = load_corpus(cli.instances.graphs.as_deref());
+
+    println!("seed: {seed}");
+
+    for (i, command) in cli.optimizers.iter().enumerate() {
+        if cli.optimizers[..i].contains(command) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{command} takes part in the tournament more than once"),
+            ));
+        }
+    }
+
+    let mut contestants = Vec::with_capacity(cli.optimizers.len());
+    for (id, command) in cli.optimizers.iter().enumerate() {
+        let mut optimizer = Optimizer::new(command, id as u32 + 1);
+        smol::spawn(optimizer.redirect_stderr()).detach();
+        contestants.push(Contestant::new(optimizer));
+    }
+    for contestant in &mut contestants {
+        contestant.name = contestant.optimizer.read_start().await?.label();
+    }
+    disambiguate_names(&mut contestants);
+
+    let count = contestants.len();
+    // scores[i][j] is the score of optimizer i against optimizer j
+    let mut scores = vec![vec![Score::default(); count]; count];
+
+    for current_instance in 0..cli.max_games {
+        let graph = next_instance(
+            &corpus,
+            cli.instances.sample,
+            cli.instances.points,
+            &mut rng,
+            current_instance,
+        )?;
+        println!("Started instance {} of {}", current_instance, cli.max_games);
+
+        // Every optimizer gets the same seed
+        let params = GraphParams {
+            time_limit_ms: cli.instances.time_limit_ms,
+            seed: Some(seed.wrapping_add(current_instance as u64)),
+        };
+
+        // All optimizers work on the instance at the same time
+        let results = {
+            let executor = LocalExecutor::new();
+            let tasks = contestants
+                .iter_mut()
+                .map(|contestant| {
+                    executor.spawn(play(&mut contestant.optimizer, &graph, &params, timeout))
+                })
+                .collect::<Vec<_>>();
+            executor
+                .run(async {
+                    let mut results = Vec::with_capacity(tasks.len());
+                    for task in tasks {
+                        results.push(task.await?);
+                    }
+                    io::Result::Ok(results)
+                })
+                .await?
+        };
+
+        let metrics = contestants
+            .iter_mut()
+            .zip(results)
+            .map(|(contestant, result)| {
+                score_result(&contestant.name, result, &mut contestant.failures)
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..count {
+            let opponents = if cli.gauntlet { 0..1 } else { 0..i };
+            for j in opponents.filter(|&j| j != i) {
+                let (first, second) = (j.min(i), j.max(i));
+                let (winner, loser) = match game_ordering(
+                    metrics[first].as_ref(),
+                    metrics[second].as_ref(),
+                    &cli.instances.tie_breaker,
+                ) {
+                    std::cmp::Ordering::Less => (first, second),
+                    std::cmp::Ordering::Greater => (second, first),
+                    std::cmp::Ordering::Equal => {
+                        scores[first][second].draws += 1;
+                        scores[second][first].draws += 1;
+                        continue;
+                    }
+                };
+                scores[winner][loser].wins += 1;
+                scores[loser][winner].losses += 1;
+            }
+        }
+
+        for contestant in &contestants {
+            if !contestant.failures.is_empty() {
+                println!(
+                    "{} failures: {}",
+                    contestant.name,
+                    format_failures(&contestant.failures)
+                );
+            }
+        }
+
+        let standings = standings(&scores);
+        print_crosstable(&contestants, &scores, &standings);
+        save_crosstable(&cli.output, &contestants, &scores, &standings)?;
+    }
+
+    Ok(())
+}
+
+/// Appends the position on the command line to names that several optimizers report,
+/// so that the crosstable tells them apart
+fn disambiguate_names(contestants: &mut [Contestant]) {
+    let names = contestants
+        .iter()
+        .map(|contestant| contestant.name.clone())
+        .collect::<Vec<_>>();
+    for (i, contestant) in contestants.iter_mut().enumerate() {
+        if names.iter().filter(|&name| *name == names[i]).count() > 1 {
+            contestant.name = format!("{} #{}", names[i], i + 1);
+        }
+    }
+}
+
+/// Virtual draws against every opponent, so that sweeps get a finite rating, like the prior of BayesElo
+const PRIOR_DRAWS: f64 = 2.0;
+
+/// Rates all optimizers together by their scores against each other, best first.
+/// Draws count as half a win. The ratings average to zero.
+fn standings(scores: &[Vec<Score>]) -> Vec<Standing> {
+    let count = scores.len();
+    let opponents = |i: usize| (0..count).filter(move |&j| scores[i][j].games() > 0);
+    let games = |i: usize, j: usize| scores[i][j].games() as f64 + PRIOR_DRAWS;
+    let points = |i: usize, j: usize| scores[i][j].points() as f64 + PRIOR_DRAWS / 2.0;
+
+    // Bradley-Terry strengths, fitted with minorization-maximization
+    let mut strengths = vec![1.0; count];
+    for _ in 0..10_000 {
+        let mut next = (0..count)
+            .map(|i| {
+                let expected = opponents(i)
+                    .map(|j| games(i, j) / (strengths[i] + strengths[j]))
+                    .sum::<f64>();
+                if expected == 0.0 {
+                    1.0
+                } else {
+                    opponents(i).map(|j| points(i, j)).sum::<f64>() / expected
+                }
+            })
+            .collect::<Vec<f64>>();
+        let mean = next.iter().map(|s| s.ln()).sum::<f64>() / count as f64;
+        next.iter_mut().for_each(|s| *s /= mean.exp());
+
+        let converged = next
+            .iter()
+            .zip(&strengths)
+            .all(|(a, b)| (a / b).ln().abs() < 1e-9);
+        strengths = next;
+        if converged {
+            break;
+        }
+    }
+
+    let elo_per_ln = 400.0 / std::f64::consts::LN_10;
+    let mut standings = (0..count)
+        .map(|index| {
+            let mut total = Score::default();
+            scores[index].iter().for_each(|score| total.add(*score));
+
+            let elo = strengths[index].ln() * elo_per_ln;
+            // 95% confidence interval from the Fisher information of the rating
+            let information = opponents(index)
+                .map(|j| {
+                    let p = strengths[index] / (strengths[index] + strengths[j]);
+                    games(index, j) * p * (1.0 - p)
+                })
+                .sum::<f64>();
+            let error = 1.96 * elo_per_ln / information.sqrt();
+            Standing {
+                index,
+                total,
+                elo: ((elo - error) as f32, elo as f32, (elo + error) as f32),
+            }
+        })
+        .collect::<Vec<_>>();
+    standings.sort_by(|a, b| b.elo.1.total_cmp(&a.elo.1));
+    standings
+}
+
+fn print_crosstable(contestants: &[Contestant], scores: &[Vec<Score>], standings: &[Standing]) {
+    let name_width = contestants
+        .iter()
+        .map(|contestant| contestant.name.len())
+        .chain(["Name".len()])
+        .max()
+        .unwrap_or_default();
+
+    print!(
+        "{:>3} {:<name_width$} {:>8} {:>8} {:>6} {:>6}",
+        "#", "Name", "Elo", "+-", "Games", "Score"
+    );
+    for rank in 1..=standings.len() {
+        print!(" {:>9}", rank);
+    }
+    println!();
+
+    for (rank, standing) in standings.iter().enumerate() {
+        let (lower, elo, upper) = standing.elo;
+        print!(
+            "{:>3} {:<name_width$} {:>8.1} {:>8.1} {:>6} {:>6.1}",
+            rank + 1,
+            contestants[standing.index].name,
+            elo,
+            (upper - lower) / 2.0,
+            standing.total.games(),
+            standing.total.points(),
+        );
+        for opponent in standings {
+            let score = scores[standing.index][opponent.index];
+            if score.games() == 0 {
+                print!(" {:>9}", "-");
+            } else {
+                print!(" {:>9}", format_score(score));
+            }
+        }
+        println!();
+    }
+}
+
+fn format_score(score: Score) -> String {
+    format!("{}/{}/{}", score.wins, score.draws, score.losses)
+}
+
+/// Writes the crosstable as a `.csv` file, with wins/draws/losses against every opponent
+fn save_crosstable(
+    path: &Path,
+    contestants: &[Contestant],
+    scores: &[Vec<Score>],
+    standings: &[Standing],
+) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    let mut header = vec![
+        "optimizer",
+        "elo",
+        "elo_lower",
+        "elo_upper",
+        "wins",
+        "draws",
+        "losses",
+    ];
+    header.extend(standings.iter().map(|s| contestants[s.index].name.as_str()));
+    writer.write_record(header)?;
+
+    for standing in standings {
+        let (lower, elo, upper) = standing.elo;
+        let mut record = vec![
+            contestants[standing.index].name.clone(),
+            elo.to_string(),
+            lower.to_string(),
+            upper.to_string(),
+            standing.total.wins.to_string(),
+            standing.total.draws.to_string(),
+            standing.total.losses.to_string(),
+        ];
+        record.extend(standings.iter().map(|opponent| {
+            let score = scores[standing.index][opponent.index];
+            if score.games() == 0 {
+                String::new()
+            } else {
+                format_score(score)
+            }
+        }));
+        writer.write_record(record)?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn score(wins: u32, draws: u32, losses: u32) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn standings_with_a_sweep() {
+        // The first optimizer wins every game, the others draw against each other
+        let scores = vec![
+            vec![score(0, 0, 0), score(10, 0, 0), score(10, 0, 0)],
+            vec![score(0, 0, 10), score(0, 0, 0), score(0, 10, 0)],
+            vec![score(0, 0, 10), score(0, 10, 0), score(0, 0, 0)],
+        ];
+        let standings = standings(&scores);
+
+        assert_eq!(standings[0].index, 0);
+        for standing in &standings {
+            let (lower, elo, upper) = standing.elo;
+            assert!(lower.is_finite() && upper.is_finite());
+            assert!(lower < elo && elo < upper);
+        }
+        assert!(standings[0].elo.1 - standings[1].elo.1 > 300.0);
+        assert!((standings[1].elo.1 - standings[2].elo.1).abs() < 1e-3);
+        let sum = standings.iter().map(|s| s.elo.1).sum::<f32>();
+        assert!(sum.abs() < 1e-3);
+    }
+
+    #[test]
+    fn standings_without_games() {
+        let scores = vec![vec![Score::default(); 2]; 2];
+        for standing in standings(&scores) {
+            assert_eq!(standing.elo.1, 0.0);
+        }
+    }
+}
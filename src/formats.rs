@@ -0,0 +1,334 @@
+//! Readers for graph formats other than our JSON, so that graphs from other tools can be added to
+//! `./graphs` without converting them by hand.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::graph::{Edge, Graph, Node};
+
+mod dot;
+mod edge_list;
+mod gml;
+mod graphml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Graphml,
+    Gml,
+    Dot,
+    /// One edge per line, with the two node names separated by whitespace
+    EdgeList,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "graphml" | "xml" => Some(Format::Graphml),
+            "gml" => Some(Format::Gml),
+            "dot" | "gv" => Some(Format::Dot),
+            "txt" | "edges" | "edgelist" | "el" => Some(Format::EdgeList),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_graph(text: &str, format: Format) -> io::Result<Graph> {
+    let raw = match format {
+        Format::Graphml => graphml::read(text)?,
+        Format::Gml => gml::read(text)?,
+        Format::Dot => dot::read(text)?,
+        Format::EdgeList => edge_list::read(text)?,
+    };
+    raw.into_graph()
+}
+
+/// Converts the files and writes them to `./graphs/<into>/<file name>.json`
+pub fn convert(files: &[PathBuf], into: &str, format: Option<Format>) -> io::Result<()> {
+    // The graphs must end up inside of ./graphs
+    if !Path::new(into)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(invalid_data(format!(
+            "--into must be a relative path without .., but is {into}"
+        )));
+    }
+    let mut output_dir = PathBuf::from("./graphs");
+    output_dir.push(into);
+
+    let outputs = files
+        .iter()
+        .map(|file| {
+            let mut path = output_dir.clone();
+            path.push(file.file_name().expect("files have a name"));
+            path.set_extension("json");
+            path
+        })
+        .collect::<Vec<_>>();
+    let mut seen = HashMap::new();
+    for (file, path) in files.iter().zip(&outputs) {
+        if let Some(other) = seen.insert(path, file) {
+            return Err(invalid_data(format!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                file.display(),
+                path.display()
+            )));
+        }
+    }
+
+    std::fs::create_dir_all(&output_dir)?;
+    for (file, path) in files.iter().zip(outputs) {
+        let format = format.or_else(|| Format::from_path(file)).ok_or_else(|| {
+            invalid_data(format!(
+                "Unknown format of {}, please specify it with --format",
+                file.display()
+            ))
+        })?;
+        let graph = read_graph(&std::fs::read_to_string(file)?, format)
+            .map_err(|e| invalid_data(format!("Failed to read {}: {}", file.display(), e)))?;
+
+        std::fs::write(&path, serde_json::to_vec_pretty(&graph)?)?;
+        println!(
+            "Converted {} ({} nodes, {} edges) to {}",
+            file.display(),
+            graph.nodes.len(),
+            graph.edges.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A graph with the node names and coordinates from the file
+#[derive(Default)]
+struct RawGraph {
+    node_indices: HashMap<String, usize>,
+    positions: Vec<Option<(f64, f64)>>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl RawGraph {
+    /// Returns the index of the node, and adds it if it doesn't exist yet
+    fn node(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.node_indices.get(name) {
+            return index;
+        }
+        let index = self.positions.len();
+        self.node_indices.insert(name.to_string(), index);
+        self.positions.push(None);
+        index
+    }
+
+    fn set_position(&mut self, name: &str, position: (f64, f64)) {
+        let index = self.node(name);
+        self.positions[index] = Some(position);
+    }
+
+    fn edge(&mut self, source: &str, target: &str) {
+        let edge = (self.node(source), self.node(target));
+        self.edges.push(edge);
+    }
+
+    /// Node IDs are assigned in the order in which the nodes appear in the file.
+    ///
+    /// Without coordinates for every node, all nodes start at `(0, 0)` on a `n` by `n` canvas,
+    /// like the rest of `./graphs`. Otherwise the coordinates are shifted to start at zero,
+    /// and scaled by powers of ten until they are integers.
+    fn into_graph(self) -> io::Result<Graph> {
+        let n = self.positions.len();
+        let positions = self.positions.into_iter().collect::<Option<Vec<_>>>();
+        let positions = match positions {
+            Some(positions) if !positions.is_empty() => integer_positions(&positions)?,
+            _ => vec![(0, 0); n],
+        };
+
+        let size = n.max(1) as u32;
+        let width = positions.iter().map(|p| p.0).max().unwrap_or_default();
+        let height = positions.iter().map(|p| p.1).max().unwrap_or_default();
+
+        Ok(Graph {
+            nodes: positions
+                .into_iter()
+                .enumerate()
+                .map(|(id, (x, y))| Node { id, x, y })
+                .collect(),
+            points: vec![],
+            edges: self
+                .edges
+                .into_iter()
+                .map(|(source, target)| Edge { source, target })
+                .collect(),
+            width: width.max(size),
+            height: height.max(size),
+        })
+    }
+}
+
+/// Fails if rounding puts nodes with different positions onto the same coordinates,
+/// or if the positions don't fit into integer coordinates
+fn integer_positions(positions: &[(f64, f64)]) -> io::Result<Vec<(u32, u32)>> {
+    const MAX_COORDINATE: f64 = 1_000_000.0;
+    let min_x = positions.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let min_y = positions.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let shifted = positions
+        .iter()
+        .map(|&(x, y)| (x - min_x, y - min_y))
+        .collect::<Vec<_>>();
+    let extent = shifted.iter().map(|p| p.0.max(p.1)).fold(0.0, f64::max);
+    if extent > u32::MAX as f64 || shifted.iter().any(|p| !p.0.is_finite() || !p.1.is_finite()) {
+        return Err(invalid_data(format!(
+            "node positions span {extent}, which doesn't fit into coordinates up to {}",
+            u32::MAX
+        )));
+    }
+
+    let is_integral = |scale: f64| {
+        shifted.iter().all(|&(x, y)| {
+            ((x * scale).round() - x * scale).abs() < 1e-6
+                && ((y * scale).round() - y * scale).abs() < 1e-6
+        })
+    };
+    let mut scale = 1.0;
+    while !is_integral(scale) && extent * scale * 10.0 <= MAX_COORDINATE {
+        scale *= 10.0;
+    }
+
+    let distinct_positions = shifted
+        .iter()
+        .map(|p| (p.0.to_bits(), p.1.to_bits()))
+        .collect::<HashSet<_>>()
+        .len();
+    let rounded = shifted
+        .into_iter()
+        .map(|(x, y)| ((x * scale).round() as u32, (y * scale).round() as u32))
+        .collect::<Vec<_>>();
+    if rounded.iter().collect::<HashSet<_>>().len() < distinct_positions {
+        return Err(invalid_data(format!(
+            "nodes at different positions would share coordinates after scaling them by {scale} to integers"
+        )));
+    }
+    Ok(rounded)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{Format, convert, read_graph};
+
+    /// Node positions and edges
+    type Summary = (Vec<(u32, u32)>, Vec<(usize, usize)>);
+
+    fn summary(text: &str, format: Format) -> Summary {
+        let graph = read_graph(text, format).unwrap();
+        (
+            graph.nodes.iter().map(|n| (n.x, n.y)).collect(),
+            graph.edges.iter().map(|e| (e.source, e.target)).collect(),
+        )
+    }
+
+    #[test]
+    fn graphml() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="d0" for="node" attr.name="x" attr.type="double"/>
+  <key id="d1" for="node" attr.name="y" attr.type="double"/>
+  <graph id="G" edgedefault="undirected">
+    <node id="a"><data key="d0">10</data><data key="d1">-5</data></node>
+    <node id="b"><data key="d0">12</data><data key="d1">0</data></node>
+    <!-- a comment -->
+    <node id="c"><data key="d0">10</data><data key="d1">3</data></node>
+    <edge source="a" target="b"/>
+    <edge source="c" target="a"/>
+  </graph>
+</graphml>"#;
+        assert_eq!(
+            summary(text, Format::Graphml),
+            (vec![(0, 0), (2, 5), (0, 8)], vec![(0, 1), (2, 0)])
+        );
+    }
+
+    #[test]
+    fn gml() {
+        let text = r#"Creator "ogdf::GraphIO::writeGML"
+graph [
+  directed 0
+  node [ id 7 graphics [ x 0.5 y 0.0 w 20 ] ]
+  node [ id 3 label "three" graphics [ x 0.0 y 1.5 ] ]
+  edge [ source 7 target 3 ]
+]"#;
+        assert_eq!(
+            summary(text, Format::Gml),
+            (vec![(5, 0), (0, 15)], vec![(0, 1)])
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let text = r#"graph G {
+  // Comments are skipped
+  node [shape=circle];
+  a [pos="1,2!"];
+  "b c" [label="B", pos="3,4"];
+  a -- "b c" -- d [color=red];
+}"#;
+        // d has no position, so nobody gets one
+        assert_eq!(
+            summary(text, Format::Dot),
+            (vec![(0, 0); 3], vec![(0, 1), (1, 2)])
+        );
+    }
+
+    #[test]
+    fn edge_list() {
+        let text = "# comment\n1 2\n2\t3 0.5\n\n% another comment\n4\n";
+        let (nodes, edges) = summary(text, Format::EdgeList);
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(edges, vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn close_coordinates_are_rejected() {
+        // Scaling the extent of 10^7 any further would go past the largest coordinate
+        let text = "graph [\n  node [ id 1 graphics [ x 0.0 y 0.0 ] ]\n  node [ id 2 graphics [ x 0.1 y 0.0 ] ]\n  node [ id 3 graphics [ x 10000000.0 y 0.0 ] ]\n]";
+        assert!(read_graph(text, Format::Gml).is_err());
+    }
+
+    #[test]
+    fn huge_coordinates_are_rejected() {
+        let text = "graph [\n  node [ id 1 graphics [ x 0.0 y 0.0 ] ]\n  node [ id 2 graphics [ x 1e12 y 0.0 ] ]\n]";
+        assert!(read_graph(text, Format::Gml).is_err());
+        let text = "graph [\n  node [ id 1 graphics [ x NaN y 0.0 ] ]\n]";
+        assert!(read_graph(text, Format::Gml).is_err());
+    }
+
+    #[test]
+    fn dot_graph_attribute_needs_a_value() {
+        assert!(read_graph("graph { rankdir =", Format::Dot).is_err());
+        assert!(read_graph("graph { rankdir = ; a }", Format::Dot).is_err());
+    }
+
+    #[test]
+    fn convert_rejects_paths_outside_of_graphs() {
+        assert!(convert(&[], "../elsewhere", None).is_err());
+        assert!(convert(&[], "/absolute", None).is_err());
+    }
+
+    #[test]
+    fn convert_rejects_colliding_outputs() {
+        let files = [PathBuf::from("a.dot"), PathBuf::from("a.gml")];
+        let error = convert(&files, "never-created", None).unwrap_err();
+        assert!(error.to_string().contains("would both be written"));
+    }
+}
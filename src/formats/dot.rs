@@ -0,0 +1,299 @@
+use std::io;
+
+use super::{RawGraph, invalid_data};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An identifier, number or string, without quotes
+    Id(String),
+    /// `graph`, `digraph`, `node`, `edge`, `subgraph` or `strict`
+    Keyword(String),
+    EdgeOp,
+    OpenBracket,
+    CloseBracket,
+    OpenBrace,
+    CloseBrace,
+    Equals,
+    Colon,
+    Separator,
+}
+
+struct Tokens {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Tokens {
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.position + n)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, condition: impl FnOnce(&Token) -> bool) -> Option<Token> {
+        if self.peek().is_some_and(condition) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    fn next_if_eq(&mut self, expected: &Token) -> Option<Token> {
+        self.next_if(|t| t == expected)
+    }
+}
+
+/// Reads the nodes and edges of a DOT file.
+/// Coordinates are taken from the `pos="x,y"` attribute of a node.
+pub(super) fn read(text: &str) -> io::Result<RawGraph> {
+    let mut tokens = Tokens {
+        tokens: tokenize(text)?,
+        position: 0,
+    };
+    tokens.next_if(|t| is_keyword(t, "strict"));
+    match tokens.next() {
+        Some(token) if is_keyword(&token, "graph") || is_keyword(&token, "digraph") => {}
+        token => return Err(invalid_data(format!("expected graph, but got {token:?}"))),
+    }
+    tokens.next_if(|t| matches!(t, Token::Id(_)));
+    expect(&mut tokens, Token::OpenBrace)?;
+
+    let mut graph = RawGraph::default();
+    parse_statements(&mut tokens, &mut graph)?;
+    Ok(graph)
+}
+
+/// Parses statements until the closing brace, and returns the nodes that were mentioned
+fn parse_statements(tokens: &mut Tokens, graph: &mut RawGraph) -> io::Result<Vec<String>> {
+    let mut mentioned = vec![];
+    loop {
+        match tokens.peek() {
+            None => return Err(invalid_data("missing }")),
+            Some(Token::CloseBrace) => {
+                tokens.next();
+                return Ok(mentioned);
+            }
+            Some(Token::Separator) => {
+                tokens.next();
+            }
+            Some(t) if is_keyword(t, "graph") || is_keyword(t, "node") || is_keyword(t, "edge") => {
+                tokens.next();
+                parse_attributes(tokens)?;
+            }
+            Some(Token::Id(_)) if tokens.peek_nth(1) == Some(&Token::Equals) => {
+                // A graph attribute like `rankdir = LR`
+                tokens.position += 2;
+                if tokens.next_if(|t| matches!(t, Token::Id(_))).is_none() {
+                    return Err(invalid_data("expected a value after ="));
+                }
+            }
+            Some(_) => mentioned.extend(parse_node_or_edges(tokens, graph)?),
+        }
+    }
+}
+
+/// Parses `a [attributes]` or `a -- b -- {c d} [attributes]`
+fn parse_node_or_edges(tokens: &mut Tokens, graph: &mut RawGraph) -> io::Result<Vec<String>> {
+    let mut previous = parse_endpoint(tokens, graph)?;
+    let mut mentioned = previous.clone();
+    let is_node_statement = !matches!(tokens.peek(), Some(Token::EdgeOp));
+
+    while tokens.next_if_eq(&Token::EdgeOp).is_some() {
+        let next = parse_endpoint(tokens, graph)?;
+        for source in &previous {
+            for target in &next {
+                graph.edge(source, target);
+            }
+        }
+        mentioned.extend(next.iter().cloned());
+        previous = next;
+    }
+
+    let attributes = parse_attributes(tokens)?;
+    if is_node_statement {
+        let position = attributes
+            .iter()
+            .find(|(key, _)| key == "pos")
+            .and_then(|(_, value)| parse_position(value));
+        if let (Some(position), [node]) = (position, mentioned.as_slice()) {
+            graph.set_position(node, position);
+        }
+    }
+    Ok(mentioned)
+}
+
+/// A single node, or all nodes of a subgraph
+fn parse_endpoint(tokens: &mut Tokens, graph: &mut RawGraph) -> io::Result<Vec<String>> {
+    match tokens.next() {
+        Some(Token::Id(id)) => {
+            // Ports like `a:north` are ignored
+            if tokens.next_if_eq(&Token::Colon).is_some() {
+                tokens.next();
+                if tokens.next_if_eq(&Token::Colon).is_some() {
+                    tokens.next();
+                }
+            }
+            graph.node(&id);
+            Ok(vec![id])
+        }
+        Some(Token::OpenBrace) => parse_statements(tokens, graph),
+        Some(token) if is_keyword(&token, "subgraph") => {
+            tokens.next_if(|t| matches!(t, Token::Id(_)));
+            expect(tokens, Token::OpenBrace)?;
+            parse_statements(tokens, graph)
+        }
+        token => Err(invalid_data(format!("expected a node, but got {token:?}"))),
+    }
+}
+
+/// Parses any number of `[key=value, ...]` lists
+fn parse_attributes(tokens: &mut Tokens) -> io::Result<Vec<(String, String)>> {
+    let mut attributes = vec![];
+    while tokens.next_if_eq(&Token::OpenBracket).is_some() {
+        loop {
+            match tokens.next() {
+                Some(Token::CloseBracket) => break,
+                Some(Token::Separator) => {}
+                Some(Token::Id(key)) => {
+                    expect(tokens, Token::Equals)?;
+                    match tokens.next() {
+                        Some(Token::Id(value)) => attributes.push((key, value)),
+                        token => {
+                            return Err(invalid_data(format!(
+                                "expected a value for {key}, but got {token:?}"
+                            )));
+                        }
+                    }
+                }
+                token => {
+                    return Err(invalid_data(format!(
+                        "expected an attribute, but got {token:?}"
+                    )));
+                }
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+/// Parses `x,y`, optionally followed by a `!`
+fn parse_position(value: &str) -> Option<(f64, f64)> {
+    let mut parts = value.trim_end_matches('!').split(',');
+    let x = parts.next()?.trim().parse().ok()?;
+    let y = parts.next()?.trim().parse().ok()?;
+    Some((x, y))
+}
+
+fn expect(tokens: &mut Tokens, expected: Token) -> io::Result<()> {
+    match tokens.next() {
+        Some(token) if token == expected => Ok(()),
+        token => Err(invalid_data(format!(
+            "expected {expected:?}, but got {token:?}"
+        ))),
+    }
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Keyword(k) if k == keyword)
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    const KEYWORDS: [&str; 6] = ["graph", "digraph", "node", "edge", "subgraph", "strict"];
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut at_line_start = true;
+    while let Some(c) = chars.next() {
+        let was_at_line_start = at_line_start;
+        at_line_start = c == '\n' || (at_line_start && c.is_whitespace());
+        match c {
+            c if c.is_whitespace() => {}
+            // Preprocessor output lines
+            '#' if was_at_line_start => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'/') => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '-' if matches!(chars.peek(), Some('-') | Some('>')) => {
+                chars.next();
+                tokens.push(Token::EdgeOp);
+            }
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            '{' => tokens.push(Token::OpenBrace),
+            '}' => tokens.push(Token::CloseBrace),
+            '=' => tokens.push(Token::Equals),
+            ':' => tokens.push(Token::Colon),
+            ';' | ',' => tokens.push(Token::Separator),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') if chars.peek() == Some(&'"') => string.push('"'),
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(invalid_data("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Id(string));
+            }
+            '<' => {
+                // HTML strings can be nested
+                let mut depth = 1;
+                let mut string = String::new();
+                for c in chars.by_ref() {
+                    depth += match c {
+                        '<' => 1,
+                        '>' => -1,
+                        _ => 0,
+                    };
+                    if depth == 0 {
+                        break;
+                    }
+                    string.push(c);
+                }
+                tokens.push(Token::Id(string));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut id = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    id.push(c);
+                    chars.next();
+                }
+                let lowercase = id.to_ascii_lowercase();
+                if KEYWORDS.contains(&lowercase.as_str()) {
+                    tokens.push(Token::Keyword(lowercase));
+                } else {
+                    tokens.push(Token::Id(id));
+                }
+            }
+            c => return Err(invalid_data(format!("unexpected character {c:?}"))),
+        }
+    }
+    Ok(tokens)
+}
+
+fn skip_line(chars: &mut impl Iterator<Item = char>) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::io;
+
+use super::RawGraph;
+
+/// Reads one edge per line, as two node names separated by whitespace.
+/// Anything after the second name, like a weight, is ignored. A line with a single name adds a
+/// node without any edges. Lines starting with `#` or `%` are comments.
+pub(super) fn read(text: &str) -> io::Result<RawGraph> {
+    let mut graph = RawGraph::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with('%') {
+            continue;
+        }
+        let mut names = line.split_whitespace();
+        match (names.next(), names.next()) {
+            (Some(source), Some(target)) => graph.edge(source, target),
+            (Some(node), None) => _ = graph.node(node),
+            _ => {}
+        }
+    }
+    Ok(graph)
+}
@@ -0,0 +1,143 @@
+use std::io;
+
+use super::{RawGraph, invalid_data};
+
+enum Token {
+    Open,
+    Close,
+    /// A key, a number or a string without its quotes
+    Word(String),
+}
+
+/// A GML value. Lists are `[ key value key value ... ]`.
+enum Value {
+    Scalar(String),
+    List(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::List(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            Value::Scalar(_) => None,
+        }
+    }
+
+    fn scalar(&self) -> Option<&str> {
+        match self {
+            Value::Scalar(v) => Some(v),
+            Value::List(_) => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        self.scalar()?.parse().ok()
+    }
+}
+
+/// Reads the nodes and edges of a GML file.
+/// Coordinates are taken from the `x` and `y` values in the `graphics` of a node.
+pub(super) fn read(text: &str) -> io::Result<RawGraph> {
+    let mut tokens = tokenize(text)?.into_iter();
+    let root = parse_list(&mut tokens, false)?;
+    let Value::List(entries) = root else {
+        unreachable!("parse_list returns a list")
+    };
+    let (_, graph_value) = entries
+        .iter()
+        .find(|(k, _)| k == "graph")
+        .ok_or_else(|| invalid_data("no graph in the GML file"))?;
+    let Value::List(graph_entries) = graph_value else {
+        return Err(invalid_data("graph is not a list"));
+    };
+
+    let mut graph = RawGraph::default();
+    for (key, value) in graph_entries {
+        match key.as_str() {
+            "node" => {
+                let id = value
+                    .get("id")
+                    .and_then(Value::scalar)
+                    .ok_or_else(|| invalid_data("node without an id"))?;
+                graph.node(id);
+                let graphics = value.get("graphics");
+                let coordinate = |name| graphics.and_then(|g| g.get(name)).and_then(Value::number);
+                if let (Some(x), Some(y)) = (coordinate("x"), coordinate("y")) {
+                    graph.set_position(id, (x, y));
+                }
+            }
+            "edge" => {
+                let endpoint = |name| value.get(name).and_then(Value::scalar);
+                match (endpoint("source"), endpoint("target")) {
+                    (Some(source), Some(target)) => graph.edge(source, target),
+                    _ => return Err(invalid_data("edge without a source or target")),
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(graph)
+}
+
+/// Parses `key value` pairs until the closing `]`, or until the end for the outermost list
+fn parse_list(tokens: &mut impl Iterator<Item = Token>, is_nested: bool) -> io::Result<Value> {
+    let mut entries = vec![];
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Word(key)) => key,
+            Some(Token::Close) if is_nested => return Ok(Value::List(entries)),
+            Some(_) => return Err(invalid_data("expected a key, but got a bracket")),
+            None if is_nested => return Err(invalid_data("missing ]")),
+            None => return Ok(Value::List(entries)),
+        };
+        let value = match tokens.next() {
+            Some(Token::Open) => parse_list(tokens, true)?,
+            Some(Token::Word(value)) => Value::Scalar(value),
+            _ => return Err(invalid_data(format!("missing value for {key}"))),
+        };
+        entries.push((key, value));
+    }
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                // Comment until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(invalid_data("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Word(string));
+            }
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '[' || c == ']' || c == '"' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(token));
+            }
+        }
+    }
+    Ok(tokens)
+}
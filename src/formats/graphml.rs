@@ -0,0 +1,61 @@
+use std::io;
+
+use super::{RawGraph, invalid_data};
+
+/// Reads the nodes and edges of a GraphML file.
+/// Coordinates are taken from `x` and `y` data keys, or from yFiles `Geometry` elements.
+pub(super) fn read(text: &str) -> io::Result<RawGraph> {
+    let document = roxmltree::Document::parse(text).map_err(|e| invalid_data(e.to_string()))?;
+
+    let key_id = |name: &str| {
+        document
+            .descendants()
+            .filter(|n| n.has_tag_name("key"))
+            .filter(|n| matches!(n.attribute("for"), None | Some("node") | Some("all")))
+            .find(|n| {
+                n.attribute("attr.name")
+                    .is_some_and(|v| v.eq_ignore_ascii_case(name))
+            })
+            .and_then(|n| n.attribute("id"))
+    };
+    let (x_key, y_key) = (key_id("x"), key_id("y"));
+
+    let mut graph = RawGraph::default();
+    for node in document.descendants().filter(|n| n.has_tag_name("node")) {
+        let id = node
+            .attribute("id")
+            .ok_or_else(|| invalid_data("node without an id"))?;
+        graph.node(id);
+
+        let data = |key: Option<&str>| {
+            node.children()
+                .filter(|n| n.has_tag_name("data"))
+                .find(|n| key.is_some() && n.attribute("key") == key)
+                .and_then(|n| n.text())
+                .and_then(|v| v.trim().parse::<f64>().ok())
+        };
+        let geometry = node
+            .descendants()
+            .find(|n| n.tag_name().name() == "Geometry")
+            .and_then(|n| {
+                Some((
+                    n.attribute("x")?.parse::<f64>().ok()?,
+                    n.attribute("y")?.parse::<f64>().ok()?,
+                ))
+            });
+
+        if let (Some(x), Some(y)) = (data(x_key), data(y_key)) {
+            graph.set_position(id, (x, y));
+        } else if let Some(position) = geometry {
+            graph.set_position(id, position);
+        }
+    }
+
+    for edge in document.descendants().filter(|n| n.has_tag_name("edge")) {
+        match (edge.attribute("source"), edge.attribute("target")) {
+            (Some(source), Some(target)) => graph.edge(source, target),
+            _ => return Err(invalid_data("edge without a source or target")),
+        }
+    }
+    Ok(graph)
+}
@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use petgraph::graph::UnGraph;
+use petgraph_gen::random_gnp_graph;
+use rand::{Rng, seq::SliceRandom};
+
+use crate::graph::{Edge, Graph, Node, Point};
+
+/// Coordinates of the generated nodes and points are in `0..COORDINATE_RANGE`
+const COORDINATE_RANGE: u32 = 1000;
+
+/// Generates a random G(n, p) graph with random coordinates.
+///
+/// With `points_per_node`, the graph also gets a set of that many points per node,
+/// and the nodes start out on distinct points.
+pub fn random_gnp_instance(rng: &mut impl Rng, points_per_node: Option<f32>) -> Graph {
+    let nodes = rng.gen_range(10..200);
+    let probability = rng.gen_range(0.1..0.8);
+
+    let graph: UnGraph<_, _, usize> = random_gnp_graph(rng, nodes, probability);
+
+    let points = match points_per_node {
+        // Every node needs its own point
+        Some(factor) => random_points(
+            rng,
+            ((graph.node_count() as f32 * factor).ceil() as usize).max(graph.node_count()),
+        ),
+        None => vec![],
+    };
+
+    let nodes = if points.is_empty() {
+        (0..graph.node_count())
+            .map(|id| Node {
+                id,
+                x: rng.gen_range(0..COORDINATE_RANGE),
+                y: rng.gen_range(0..COORDINATE_RANGE),
+            })
+            .collect()
+    } else {
+        points
+            .choose_multiple(rng, graph.node_count())
+            .enumerate()
+            .map(|(id, point)| Node {
+                id,
+                x: point.x,
+                y: point.y,
+            })
+            .collect()
+    };
+
+    Graph {
+        nodes,
+        points,
+        edges: graph
+            .raw_edges()
+            .iter()
+            .map(|e| Edge {
+                source: e.source().index(),
+                target: e.target().index(),
+            })
+            .collect(),
+        width: 1_000_000,
+        height: 1_000_000,
+    }
+}
+
+/// Generates `count` points at distinct coordinates
+fn random_points(rng: &mut impl Rng, count: usize) -> Vec<Point> {
+    let count = count.min((COORDINATE_RANGE * COORDINATE_RANGE) as usize);
+    let mut coordinates = HashSet::with_capacity(count);
+    while coordinates.len() < count {
+        coordinates.insert((
+            rng.gen_range(0..COORDINATE_RANGE),
+            rng.gen_range(0..COORDINATE_RANGE),
+        ));
+    }
+
+    let mut coordinates = coordinates.into_iter().collect::<Vec<_>>();
+    // Iterating over a HashSet is not deterministic
+    coordinates.sort();
+    coordinates.shuffle(rng);
+    coordinates
+        .into_iter()
+        .enumerate()
+        .map(|(id, (x, y))| Point { id, x, y })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::random_gnp_instance;
+
+    #[test]
+    fn edges_reference_existing_nodes() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        for points_per_node in [None, Some(0.5), Some(1.0), Some(3.0)] {
+            let graph = random_gnp_instance(&mut rng, points_per_node);
+            for edge in &graph.edges {
+                assert!(edge.source < graph.nodes.len());
+                assert!(edge.target < graph.nodes.len());
+            }
+            if points_per_node.is_some() {
+                assert!(graph.points.len() >= graph.nodes.len());
+            }
+            graph.crossings();
+        }
+    }
+}
@@ -1,16 +1,29 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::cmp::Ordering;
 
-use serde::de::Error;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+mod crossing_index;
+mod crossing_report;
+mod crossings;
+mod metrics;
+mod structure;
+mod validation;
+
+pub use crossing_index::{CrossingDelta, CrossingIndex};
+pub use crossing_report::{CrossingReport, EdgeCrossing, WorstEdge};
+pub use metrics::{QualityMetrics, TieBreaker};
+pub use structure::GraphMismatch;
+pub use validation::{GraphValidationError, GraphViolation};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Node {
     pub id: usize,
     pub x: u32,
     pub y: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A position where nodes may be placed
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Point {
     pub id: usize,
     pub x: u32,
@@ -34,10 +47,11 @@ pub struct CrossingCountingResult {
     pub max_per_edge: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Graph {
     pub nodes: Vec<Node>,
 
+    /// If this is not empty, every node must be placed on a distinct point
     #[serde(default)]
     pub points: Vec<Point>,
 
@@ -56,35 +70,16 @@ impl Graph {
     }
 
     pub fn crossings(&self) -> CrossingCountingResult {
-        let edges = self
-            .edges
-            .iter()
-            .map(|edge| {
-                let source = &self.nodes[edge.source];
-                let target = &self.nodes[edge.target];
-
-                SimpleEdge {
-                    source: (source.x, source.y),
-                    target: (target.x, target.y),
-                }
-            })
-            .collect::<Vec<_>>();
+        let edges = self.simple_edges();
 
         let mut total_crossings = 0;
         let mut crossings_per_edge = vec![0u32; self.edges.len()];
 
-        for (i1, e1) in edges.iter().enumerate() {
-            let mut crossings = 0;
-            for (i2, e2) in edges.iter().enumerate().skip(i1 + 1) {
-                if is_crossing(e1.source, e1.target, e2.source, e2.target) {
-                    crossings_per_edge[i1] += 1;
-                    crossings_per_edge[i2] += 1;
-                    crossings += 1;
-                }
-            }
-
-            total_crossings += crossings;
-        }
+        crossings::for_each_crossing(&edges, |i1, i2| {
+            crossings_per_edge[i1] += 1;
+            crossings_per_edge[i2] += 1;
+            total_crossings += 1;
+        });
 
         CrossingCountingResult {
             total: total_crossings,
@@ -92,142 +87,33 @@ impl Graph {
         }
     }
 
-    pub fn is_valid(&self) -> Result<(), serde_json::error::Error> {
-        let num_nodes = self.nodes.len();
-
-        type NodeID = usize;
-        type Coordinate = (u32, u32);
-
-        let mut coordinates: HashMap<Coordinate, NodeID> = HashMap::with_capacity(num_nodes);
-
-        for node in &self.nodes {
-            if node.id >= num_nodes {
-                return Err(serde_json::Error::custom(format!(
-                    "Node ID {} is out of bounds (0 to {})",
-                    node.id,
-                    num_nodes - 1
-                )));
-            }
-            if node.x > self.width {
-                return Err(serde_json::Error::custom(format!(
-                    "Node x-coordinate {} exceeds maximum width {}",
-                    node.x, self.width
-                )));
-            }
-            if node.y > self.height {
-                return Err(serde_json::Error::custom(format!(
-                    "Node y-coordinate {} exceeds maximum height {}",
-                    node.y, self.height
-                )));
-            }
-
-            if let Some(duplicate_id) = coordinates.get(&(node.x, node.y)) {
-                return Err(serde_json::Error::custom(format!(
-                    "Node with ID {} overlaps with node with ID {} at coordinates ({}, {})",
-                    duplicate_id, node.id, node.x, node.y
-                )));
-            }
-
-            coordinates.insert((node.x, node.y), node.id);
-        }
-
-        // To deal with a nodes array that is not sorted by the id
-        let mut id_to_idx = vec![None; num_nodes];
-        let mut nodes_defined = 0;
-        for (idx, node) in self.nodes.iter().enumerate() {
-            if id_to_idx[node.id].is_some() {
-                return Err(serde_json::Error::custom(format!(
-                    "Node {} is defined more than once",
-                    node.id,
-                )));
-            }
-            id_to_idx[node.id] = Some(idx);
-            nodes_defined += 1;
-        }
-
-        if nodes_defined != num_nodes {
-            return Err(serde_json::Error::custom(format!(
-                "Node ID domain mismatch: expected {num_nodes} unique IDs, found {nodes_defined}",
-            )));
-        }
-
-        for edge in &self.edges {
-            if edge.source >= num_nodes {
-                return Err(serde_json::Error::custom(format!(
-                    "Edge source {} is out of bounds (0 to {})",
-                    edge.source,
-                    num_nodes - 1
-                )));
-            }
-            if edge.target >= num_nodes {
-                return Err(serde_json::Error::custom(format!(
-                    "Edge target {} is out of bounds (0 to {})",
-                    edge.target,
-                    num_nodes - 1
-                )));
-            }
-
-            for node in &self.nodes {
-                if node.id == edge.source || node.id == edge.target {
-                    continue;
-                }
-
-                // We expect the IDs to be valid at this point, since we have validated that all
-                // nodes are uniquely defined and that there are `num_nodes` many nodes.
-                let source_idx = id_to_idx[edge.source].expect("Invalid edge source id");
-                let target_idx = id_to_idx[edge.target].expect("Invalid edge target id");
-
-                let from = &self.nodes[source_idx];
-                let to = &self.nodes[target_idx];
-
-                if !is_between((from.x, from.y), (node.x, node.y), (to.x, to.y)) {
-                    continue;
-                }
+    fn simple_edges(&self) -> Vec<SimpleEdge> {
+        self.edges
+            .iter()
+            .map(|edge| {
+                let source = &self.nodes[edge.source];
+                let target = &self.nodes[edge.target];
 
-                if is_collinear((from.x, from.y), (node.x, node.y), (to.x, to.y)) {
-                    return Err(serde_json::Error::custom(format!(
-                        "Node {} is collinear with edge from node {} ({},{}) to node {} ({},{}) at coordinates ({}, {})",
-                        node.id,
-                        edge.source,
-                        from.x,
-                        from.y,
-                        edge.target,
-                        to.x,
-                        to.y,
-                        node.x,
-                        node.y
-                    )));
+                SimpleEdge {
+                    source: (source.x, source.y),
+                    target: (target.x, target.y),
                 }
-            }
-        }
-
-        Ok(())
-    }
-
-    pub fn is_isomorphic(&self, graph: &Graph) -> bool {
-        if self.nodes.len() != graph.nodes.len() {
-            return false;
-        }
-
-        if self.edges.len() != graph.edges.len() {
-            return false;
-        }
-
-        // Hmm, I bet there's a faster option
-        let mut input_edges = self.edges.clone();
-        let mut output_edges = graph.edges.clone();
-        input_edges.sort();
-        output_edges.sort();
-
-        return input_edges == output_edges;
+            })
+            .collect()
     }
 }
 
+/// Checks if `(n, m)` is in the bounding box of the other two points, including its border
 fn is_between((a, b): (u32, u32), (n, m): (u32, u32), (x, y): (u32, u32)) -> bool {
     let [min_x, max_x] = minmax(a, x);
     let [min_y, max_y] = minmax(b, y);
 
-    (min_x < n && n < max_x) && (min_y < m && m < max_y)
+    (min_x <= n && n <= max_x) && (min_y <= m && m <= max_y)
+}
+
+/// Checks if `q` lies on the segment from `p1` to `p2`, excluding the endpoints
+fn is_on_segment(p1: (u32, u32), q: (u32, u32), p2: (u32, u32)) -> bool {
+    q != p1 && q != p2 && is_between(p1, q, p2) && is_collinear(p1, q, p2)
 }
 
 fn ccw((a, b): (u32, u32), (n, m): (u32, u32), (x, y): (u32, u32)) -> std::cmp::Ordering {
@@ -237,14 +123,15 @@ fn ccw((a, b): (u32, u32), (n, m): (u32, u32), (x, y): (u32, u32)) -> std::cmp::
     let m = m as i64;
     let x = x as i64;
     let y = y as i64;
-    return ((n - a) * (y - m)).cmp(&((m - b) * (x - n)));
+    ((n - a) * (y - m)).cmp(&((m - b) * (x - n)))
 }
 
 fn is_collinear(p1: (u32, u32), q: (u32, u32), p2: (u32, u32)) -> bool {
     ccw(p1, q, p2) == Ordering::Equal
 }
 
-/// This assumes that no three points of p1,q1,p2,p2 are collinear
+/// This assumes that no three points of p1,q1,p2,p2 are collinear.
+/// [`Graph::is_valid`] reports the drawings where that is not the case.
 fn is_crossing(p1: (u32, u32), q1: (u32, u32), p2: (u32, u32), q2: (u32, u32)) -> bool {
     if p1 == p2 || p1 == q2 || q1 == p2 || q1 == q2 {
         return false;
@@ -269,7 +156,11 @@ where
 
 #[cfg(test)]
 mod test {
-    use crate::graph::ccw;
+    use crate::graph::{
+        CrossingCountingResult, CrossingIndex, Edge, Graph, GraphViolation, Node, Point,
+        TieBreaker, ccw, is_crossing,
+    };
+    use rand::{Rng, SeedableRng};
     use std::cmp::Ordering;
 
     #[test]
@@ -279,4 +170,343 @@ mod test {
         let c = (1, 1);
         assert_eq!(ccw(a, b, c), Ordering::Less);
     }
+
+    fn brute_force_crossings(graph: &Graph) -> CrossingCountingResult {
+        let point = |id: usize| (graph.nodes[id].x, graph.nodes[id].y);
+        let mut total = 0;
+        let mut crossings_per_edge = vec![0u32; graph.edges.len()];
+        for (i1, e1) in graph.edges.iter().enumerate() {
+            for (i2, e2) in graph.edges.iter().enumerate().skip(i1 + 1) {
+                if is_crossing(
+                    point(e1.source),
+                    point(e1.target),
+                    point(e2.source),
+                    point(e2.target),
+                ) {
+                    crossings_per_edge[i1] += 1;
+                    crossings_per_edge[i2] += 1;
+                    total += 1;
+                }
+            }
+        }
+        CrossingCountingResult {
+            total,
+            max_per_edge: crossings_per_edge.into_iter().max().unwrap_or_default(),
+        }
+    }
+
+    fn random_graph(rng: &mut impl Rng, nodes: usize, edges: usize, size: u32) -> Graph {
+        Graph {
+            nodes: (0..nodes)
+                .map(|id| Node {
+                    id,
+                    x: rng.gen_range(0..size),
+                    y: rng.gen_range(0..size),
+                })
+                .collect(),
+            points: vec![],
+            edges: (0..edges)
+                .map(|_| Edge {
+                    source: rng.gen_range(0..nodes),
+                    target: rng.gen_range(0..nodes),
+                })
+                .collect(),
+            width: size,
+            height: size,
+        }
+    }
+
+    #[test]
+    fn crossings_match_brute_force() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        // Small coordinate ranges produce lots of collinear and touching edges
+        for size in [3, 10, 1000, 1_000_000] {
+            for _ in 0..50 {
+                let nodes = rng.gen_range(2..40);
+                let edges = rng.gen_range(0..120);
+                let graph = random_graph(&mut rng, nodes, edges, size);
+
+                let expected = brute_force_crossings(&graph);
+                let actual = graph.crossings();
+                assert_eq!(actual.total, expected.total, "total crossings, size {size}");
+                assert_eq!(
+                    actual.max_per_edge, expected.max_per_edge,
+                    "max crossings, size {size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crossing_index_follows_node_moves() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(7);
+        for size in [5, 1000] {
+            let mut graph = random_graph(&mut rng, 30, 80, size);
+            let mut index = CrossingIndex::new(&graph);
+
+            for _ in 0..200 {
+                let node = rng.gen_range(0..graph.nodes.len());
+                let (x, y) = (rng.gen_range(0..size), rng.gen_range(0..size));
+                let before = graph.crossings();
+
+                let predicted = index.delta_if_moved(node, x, y);
+                let actual = index.move_node(node, x, y);
+                assert_eq!(predicted, actual);
+
+                graph.nodes[node].x = x;
+                graph.nodes[node].y = y;
+                let after = graph.crossings();
+                assert_eq!(index.total(), after.total);
+                assert_eq!(index.max_per_edge(), after.max_per_edge);
+                assert_eq!(actual.total, after.total as i64 - before.total as i64);
+                assert_eq!(
+                    actual.max_per_edge,
+                    after.max_per_edge as i64 - before.max_per_edge as i64
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn is_valid_collects_all_violations() {
+        let graph = Graph {
+            nodes: vec![
+                Node { id: 0, x: 0, y: 0 },
+                Node { id: 1, x: 4, y: 4 },
+                Node { id: 2, x: 2, y: 2 },
+                Node { id: 2, x: 2, y: 2 },
+                Node { id: 9, x: 20, y: 1 },
+            ],
+            points: vec![],
+            edges: vec![
+                Edge {
+                    source: 0,
+                    target: 1,
+                },
+                Edge {
+                    source: 0,
+                    target: 7,
+                },
+            ],
+            width: 10,
+            height: 10,
+        };
+
+        let kinds = graph.is_valid().unwrap_err().kinds();
+        assert_eq!(
+            kinds,
+            vec![
+                "overlapping_nodes",
+                "node_id_out_of_bounds",
+                "out_of_bounds",
+                "duplicate_node_id",
+                "missing_node_id",
+                "node_on_edge",
+                "edge_endpoint_out_of_bounds",
+            ]
+        );
+    }
+
+    fn drawing(positions: &[(u32, u32)], edges: &[(usize, usize)]) -> Graph {
+        Graph {
+            nodes: positions
+                .iter()
+                .enumerate()
+                .map(|(id, &(x, y))| Node { id, x, y })
+                .collect(),
+            points: vec![],
+            edges: edges
+                .iter()
+                .map(|&(source, target)| Edge { source, target })
+                .collect(),
+            width: 100,
+            height: 100,
+        }
+    }
+
+    fn violation_kinds(graph: &Graph) -> Vec<&'static str> {
+        graph
+            .is_valid()
+            .err()
+            .map(|e| e.kinds())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn node_on_edge_is_exact() {
+        // Horizontal, vertical and diagonal edges with a node in the middle
+        for middle in [(2, 0), (0, 2), (2, 2)] {
+            let end = (middle.0 * 2, middle.1 * 2);
+            let graph = drawing(&[(0, 0), end, middle], &[(0, 1)]);
+            assert_eq!(violation_kinds(&graph), vec!["node_on_edge"], "{middle:?}");
+        }
+
+        // Collinear, but past the end of the edge
+        assert!(
+            drawing(&[(0, 0), (4, 0), (6, 0)], &[(0, 1)])
+                .is_valid()
+                .is_ok()
+        );
+        assert!(
+            drawing(&[(0, 0), (0, 4), (0, 5)], &[(0, 1)])
+                .is_valid()
+                .is_ok()
+        );
+        // Inside the bounding box, but not on the edge
+        assert!(
+            drawing(&[(0, 0), (4, 4), (2, 3)], &[(0, 1)])
+                .is_valid()
+                .is_ok()
+        );
+        // Right next to a long edge
+        assert!(
+            drawing(&[(0, 0), (99, 98), (98, 97)], &[(0, 1)])
+                .is_valid()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn overlapping_edges() {
+        // Partially overlapping
+        let graph = drawing(&[(0, 0), (4, 0), (2, 0), (6, 0)], &[(0, 1), (2, 3)]);
+        let error = graph.is_valid().unwrap_err();
+        assert!(
+            error
+                .violations
+                .contains(&GraphViolation::OverlappingEdges {
+                    first: 0,
+                    second: 1,
+                    from: (2, 0),
+                    to: (4, 0),
+                })
+        );
+
+        // One edge contains the other, vertically
+        let graph = drawing(&[(0, 0), (0, 6), (0, 2), (0, 4)], &[(0, 1), (3, 2)]);
+        assert!(violation_kinds(&graph).contains(&"overlapping_edges"));
+
+        // Collinear edges that only touch at a shared node
+        let graph = drawing(&[(0, 0), (2, 2), (4, 4)], &[(0, 1), (1, 2)]);
+        assert!(graph.is_valid().is_ok());
+
+        // Collinear edges with a gap in between
+        let graph = drawing(&[(0, 0), (1, 2), (3, 6), (4, 8)], &[(0, 1), (2, 3)]);
+        assert!(graph.is_valid().is_ok());
+
+        // Parallel edges between the same nodes cannot be avoided
+        let graph = drawing(&[(0, 0), (3, 1)], &[(0, 1), (1, 0)]);
+        assert!(graph.is_valid().is_ok());
+    }
+
+    #[test]
+    fn nodes_on_points() {
+        let mut graph = drawing(&[(0, 0), (3, 1), (5, 5)], &[(0, 1), (1, 2)]);
+        graph.points = [(0, 0), (3, 1), (5, 5), (9, 9)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (x, y))| Point { id, x, y })
+            .collect();
+        assert!(graph.is_valid().is_ok());
+
+        graph.nodes[2].x = 6;
+        assert_eq!(
+            graph.is_valid().unwrap_err().violations,
+            vec![GraphViolation::NodeNotOnPoint {
+                node: 2,
+                x: 6,
+                y: 5
+            }]
+        );
+
+        graph.nodes[2].x = 3;
+        graph.nodes[2].y = 1;
+        assert_eq!(violation_kinds(&graph), vec!["overlapping_nodes"]);
+    }
+
+    #[test]
+    fn same_structure_ignores_edge_direction() {
+        let input = drawing(&[(0, 0), (1, 0), (0, 1)], &[(0, 1), (1, 2), (1, 2)]);
+        let output = drawing(&[(5, 5), (1, 3), (0, 9)], &[(2, 1), (1, 0), (1, 2)]);
+        assert_eq!(input.same_structure(&output), Ok(()));
+
+        let output = drawing(&[(5, 5), (1, 3), (0, 9)], &[(2, 1), (1, 0), (0, 2)]);
+        let mismatch = input.same_structure(&output).unwrap_err();
+        assert_eq!(
+            mismatch.missing_edges,
+            vec![Edge {
+                source: 1,
+                target: 2
+            }]
+        );
+        assert_eq!(
+            mismatch.unexpected_edges,
+            vec![Edge {
+                source: 0,
+                target: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn isomorphism_finds_relabeling() {
+        // A path 0-1-2-3 with a parallel edge, relabeled to 3-0-2-1
+        let input = drawing(&[(0, 0); 4], &[(0, 1), (1, 2), (2, 3), (2, 3)]);
+        let output = drawing(&[(0, 0); 4], &[(3, 0), (0, 2), (2, 1), (1, 2)]);
+        assert!(input.same_structure(&output).is_err());
+
+        let relabeling = input.find_isomorphism(&output).unwrap();
+        assert_eq!(relabeling, vec![3, 0, 2, 1]);
+
+        // The parallel edge is in the wrong place
+        let output = drawing(&[(0, 0); 4], &[(3, 0), (0, 2), (0, 2), (2, 1)]);
+        assert_eq!(input.find_isomorphism(&output), None);
+    }
+
+    #[test]
+    fn crossing_report_lists_worst_edges() {
+        // Edge 0 is crossed by edges 1 and 2, which do not cross each other
+        let graph = drawing(
+            &[(0, 2), (10, 2), (2, 0), (2, 4), (6, 0), (6, 4)],
+            &[(0, 1), (2, 3), (4, 5)],
+        );
+        let report = graph.crossing_report();
+        assert_eq!(report.total, 2);
+        assert_eq!(report.max_per_edge, 2);
+        assert_eq!(report.per_edge, vec![2, 1, 1]);
+        assert_eq!(report.worst_edges.len(), 1);
+
+        let worst = &report.worst_edges[0];
+        assert_eq!(worst.edge, 0);
+        let partners = worst
+            .crossings
+            .iter()
+            .map(|c| (c.edge, c.point))
+            .collect::<Vec<_>>();
+        assert_eq!(partners, vec![(1, (2.0, 2.0)), (2, (6.0, 2.0))]);
+    }
+
+    #[test]
+    fn quality_metrics() {
+        // A plus sign with a shallow diagonal through it
+        let graph = drawing(
+            &[(0, 2), (4, 2), (2, 0), (2, 4), (0, 1), (4, 3)],
+            &[(0, 1), (2, 3), (4, 5)],
+        );
+        let metrics = graph.quality_metrics();
+        assert_eq!(metrics.total, 3);
+        assert_eq!(metrics.max_per_edge, 2);
+        assert_eq!(metrics.edges_at_max, 3);
+        let expected_angle = (1.0f64 / 2.0).atan().to_degrees();
+        assert!((metrics.min_crossing_angle - expected_angle).abs() < 1e-9);
+        assert!((metrics.bounding_box_usage - 16.0 / 10_000.0).abs() < 1e-12);
+
+        let mut better = metrics;
+        better.edges_at_max = 1;
+        assert_eq!(metrics.compare(&better, &[]), Ordering::Equal);
+        assert_eq!(
+            metrics.compare(&better, &[TieBreaker::Total, TieBreaker::EdgesAtMax]),
+            Ordering::Greater
+        );
+    }
 }
@@ -0,0 +1,202 @@
+//! Keeps track of the crossings of every edge, so that moving a single node does not require
+//! recounting the crossings of the whole graph.
+
+use super::{Graph, SimpleEdge, crossings, is_crossing};
+
+/// Per-edge crossing counts of a drawing, which can be updated one node move at a time.
+///
+/// Nodes and edges are referred to by their index in [`Graph::nodes`] and [`Graph::edges`].
+pub struct CrossingIndex {
+    positions: Vec<(u32, u32)>,
+    edges: Vec<(usize, usize)>,
+    incident_edges: Vec<Vec<usize>>,
+    crossings_per_edge: Vec<u32>,
+    /// How many edges have exactly that many crossings
+    edges_with_crossings: Vec<u32>,
+    total: u32,
+    max_per_edge: u32,
+}
+
+/// How the crossing numbers change when a node gets moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrossingDelta {
+    pub total: i64,
+    pub max_per_edge: i64,
+}
+
+impl CrossingIndex {
+    pub fn new(graph: &Graph) -> Self {
+        let positions = graph.nodes.iter().map(|n| (n.x, n.y)).collect::<Vec<_>>();
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| (e.source, e.target))
+            .collect::<Vec<_>>();
+
+        let mut incident_edges = vec![vec![]; positions.len()];
+        for (index, &(source, target)) in edges.iter().enumerate() {
+            incident_edges[source].push(index);
+            if source != target {
+                incident_edges[target].push(index);
+            }
+        }
+
+        let simple_edges = edges
+            .iter()
+            .map(|&(source, target)| SimpleEdge {
+                source: positions[source],
+                target: positions[target],
+            })
+            .collect::<Vec<_>>();
+
+        let mut total = 0;
+        let mut crossings_per_edge = vec![0u32; edges.len()];
+        crossings::for_each_crossing(&simple_edges, |i1, i2| {
+            crossings_per_edge[i1] += 1;
+            crossings_per_edge[i2] += 1;
+            total += 1;
+        });
+
+        let max_per_edge = crossings_per_edge.iter().copied().max().unwrap_or_default();
+        let mut edges_with_crossings = vec![0u32; max_per_edge as usize + 1];
+        for &crossings in &crossings_per_edge {
+            edges_with_crossings[crossings as usize] += 1;
+        }
+
+        Self {
+            positions,
+            edges,
+            incident_edges,
+            crossings_per_edge,
+            edges_with_crossings,
+            total,
+            max_per_edge,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn max_per_edge(&self) -> u32 {
+        self.max_per_edge
+    }
+
+    pub fn crossings_per_edge(&self) -> &[u32] {
+        &self.crossings_per_edge
+    }
+
+    pub fn position(&self, node: usize) -> (u32, u32) {
+        self.positions[node]
+    }
+
+    /// Computes what would happen if the node was moved, without moving it
+    pub fn delta_if_moved(&self, node: usize, x: u32, y: u32) -> CrossingDelta {
+        let changes = self.changes_if_moved(node, (x, y));
+        let total = changes.iter().map(|&(_, change)| change).sum::<i64>() / 2;
+
+        // The new maximum is either one of the changed edges, or the highest count that still has
+        // an unchanged edge
+        let mut removed = vec![0u32; self.edges_with_crossings.len()];
+        let mut max_per_edge = 0;
+        for &(edge, change) in &changes {
+            let old = self.crossings_per_edge[edge];
+            removed[old as usize] += 1;
+            max_per_edge = max_per_edge.max((old as i64 + change) as u32);
+        }
+        let unchanged_max = (0..=self.max_per_edge)
+            .rev()
+            .find(|&c| self.edges_with_crossings[c as usize] > removed[c as usize])
+            .unwrap_or_default();
+        max_per_edge = max_per_edge.max(unchanged_max);
+
+        CrossingDelta {
+            total,
+            max_per_edge: max_per_edge as i64 - self.max_per_edge as i64,
+        }
+    }
+
+    /// Moves the node and updates all crossing counts
+    pub fn move_node(&mut self, node: usize, x: u32, y: u32) -> CrossingDelta {
+        let old_total = self.total;
+        let old_max = self.max_per_edge;
+
+        let mut total_change = 0;
+        for (edge, change) in self.changes_if_moved(node, (x, y)) {
+            let old = self.crossings_per_edge[edge];
+            let new = (old as i64 + change) as u32;
+            self.crossings_per_edge[edge] = new;
+            total_change += change;
+
+            self.edges_with_crossings[old as usize] -= 1;
+            if new as usize >= self.edges_with_crossings.len() {
+                self.edges_with_crossings.resize(new as usize + 1, 0);
+            }
+            self.edges_with_crossings[new as usize] += 1;
+            self.max_per_edge = self.max_per_edge.max(new);
+        }
+        // Every crossing got counted once for each of its two edges
+        self.total = (old_total as i64 + total_change / 2) as u32;
+        while self.max_per_edge > 0 && self.edges_with_crossings[self.max_per_edge as usize] == 0 {
+            self.max_per_edge -= 1;
+        }
+        self.positions[node] = (x, y);
+
+        CrossingDelta {
+            total: self.total as i64 - old_total as i64,
+            max_per_edge: self.max_per_edge as i64 - old_max as i64,
+        }
+    }
+
+    /// Lists how the crossing count of every affected edge changes, one entry per edge
+    fn changes_if_moved(&self, node: usize, to: (u32, u32)) -> Vec<(usize, i64)> {
+        let mut changes = vec![];
+        for &moved_edge in &self.incident_edges[node] {
+            let old = self.simple_edge(moved_edge, None);
+            let new = self.simple_edge(moved_edge, Some((node, to)));
+
+            for (edge, &(source, target)) in self.edges.iter().enumerate() {
+                // Edges at the same node can never cross each other
+                if source == node || target == node {
+                    continue;
+                }
+                let other = self.simple_edge(edge, None);
+                let was_crossing = is_crossing(old.source, old.target, other.source, other.target);
+                let is_now_crossing =
+                    is_crossing(new.source, new.target, other.source, other.target);
+
+                let change = match (was_crossing, is_now_crossing) {
+                    (true, false) => -1,
+                    (false, true) => 1,
+                    _ => continue,
+                };
+                changes.push((moved_edge, change));
+                changes.push((edge, change));
+            }
+        }
+
+        changes.sort_unstable_by_key(|&(edge, _)| edge);
+        changes.dedup_by(|(edge, change), (previous_edge, previous_change)| {
+            if edge == previous_edge {
+                *previous_change += *change;
+                true
+            } else {
+                false
+            }
+        });
+        changes.retain(|&(_, change)| change != 0);
+        changes
+    }
+
+    fn simple_edge(&self, edge: usize, moved: Option<(usize, (u32, u32))>) -> SimpleEdge {
+        let position = |node: usize| match moved {
+            Some((moved_node, to)) if moved_node == node => to,
+            _ => self.positions[node],
+        };
+        let (source, target) = self.edges[edge];
+        SimpleEdge {
+            source: position(source),
+            target: position(target),
+        }
+    }
+}
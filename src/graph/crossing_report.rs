@@ -0,0 +1,79 @@
+use super::{Graph, crossings};
+
+/// Everything about the crossings of a drawing, for figuring out what to improve
+#[derive(Debug, Clone)]
+pub struct CrossingReport {
+    pub total: u32,
+    pub max_per_edge: u32,
+    /// The number of crossings of every edge, in the same order as [`Graph::edges`]
+    pub per_edge: Vec<u32>,
+    /// The edges with `max_per_edge` crossings. Empty if there are no crossings.
+    pub worst_edges: Vec<WorstEdge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorstEdge {
+    /// Index in [`Graph::edges`]
+    pub edge: usize,
+    pub crossings: Vec<EdgeCrossing>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EdgeCrossing {
+    /// Index of the other edge in [`Graph::edges`]
+    pub edge: usize,
+    pub point: (f64, f64),
+}
+
+impl Graph {
+    /// Like [`Graph::crossings`], but also finds out which edges are at the maximum,
+    /// and what they cross
+    pub fn crossing_report(&self) -> CrossingReport {
+        let edges = self.simple_edges();
+
+        let mut total = 0;
+        let mut per_edge = vec![0u32; edges.len()];
+        crossings::for_each_crossing(&edges, |i1, i2| {
+            per_edge[i1] += 1;
+            per_edge[i2] += 1;
+            total += 1;
+        });
+        let max_per_edge = per_edge.iter().copied().max().unwrap_or_default();
+
+        // Storing every crossing could take a lot of memory, so we go over them a second time
+        let mut worst_edges = vec![];
+        if max_per_edge > 0 {
+            let mut worst_index = vec![None; edges.len()];
+            for (edge, &crossings) in per_edge.iter().enumerate() {
+                if crossings == max_per_edge {
+                    worst_index[edge] = Some(worst_edges.len());
+                    worst_edges.push(WorstEdge {
+                        edge,
+                        crossings: vec![],
+                    });
+                }
+            }
+
+            crossings::for_each_crossing(&edges, |i1, i2| {
+                for (worst, other) in [(i1, i2), (i2, i1)] {
+                    if let Some(index) = worst_index[worst] {
+                        worst_edges[index].crossings.push(EdgeCrossing {
+                            edge: other,
+                            point: crossings::intersection_point(&edges[i1], &edges[i2]),
+                        });
+                    }
+                }
+            });
+            for worst in &mut worst_edges {
+                worst.crossings.sort_by_key(|c| c.edge);
+            }
+        }
+
+        CrossingReport {
+            total,
+            max_per_edge,
+            per_edge,
+            worst_edges,
+        }
+    }
+}
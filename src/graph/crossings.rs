@@ -0,0 +1,195 @@
+//! Counts crossings with a uniform grid instead of comparing every pair of edges.
+//!
+//! Every edge is put into all grid cells that it touches. Then only the edges that share a cell are
+//! compared. A pair of edges can share many cells, so a crossing only gets reported by the cell
+//! that contains the exact intersection point.
+
+use super::{SimpleEdge, is_crossing};
+
+/// Calls `on_crossing(i, j)` exactly once for every pair of crossing edges, with `i < j`.
+///
+/// Uses the same [`is_crossing`] predicate as a brute force comparison of all edge pairs would.
+pub fn for_each_crossing(edges: &[SimpleEdge], mut on_crossing: impl FnMut(usize, usize)) {
+    if edges.len() < 2 {
+        return;
+    }
+
+    let grid = Grid::new(edges);
+    let cells = grid.bucket_edges(edges);
+
+    // Long edges end up in lots of cells. For drawings where most edges are long, there are about
+    // as many crossings as edge pairs, and the grid cannot beat comparing all pairs.
+    let edge_pairs = pair_count(edges.len());
+    let grid_pairs: usize = cells.iter().map(|cell| pair_count(cell.len())).sum();
+    if grid_pairs > edge_pairs / 2 {
+        for (i1, e1) in edges.iter().enumerate() {
+            for (i2, e2) in edges.iter().enumerate().skip(i1 + 1) {
+                if is_crossing(e1.source, e1.target, e2.source, e2.target) {
+                    on_crossing(i1, i2);
+                }
+            }
+        }
+        return;
+    }
+
+    for (cell, cell_edges) in cells.iter().enumerate() {
+        for (a, &i1) in cell_edges.iter().enumerate() {
+            let e1 = &edges[i1];
+            for &i2 in &cell_edges[a + 1..] {
+                let e2 = &edges[i2];
+                if !bounding_boxes_overlap(e1, e2)
+                    || !is_crossing(e1.source, e1.target, e2.source, e2.target)
+                {
+                    continue;
+                }
+
+                // Only the cell with the intersection point gets to report the crossing
+                if grid.intersection_cell(e1, e2) != cell {
+                    continue;
+                }
+
+                on_crossing(i1.min(i2), i1.max(i2));
+            }
+        }
+    }
+}
+
+/// The point where two crossing edges intersect
+pub fn intersection_point(e1: &SimpleEdge, e2: &SimpleEdge) -> (f64, f64) {
+    let p = (e1.source.0 as f64, e1.source.1 as f64);
+    let r = (e1.target.0 as f64 - p.0, e1.target.1 as f64 - p.1);
+    let s = (
+        e2.target.0 as f64 - e2.source.0 as f64,
+        e2.target.1 as f64 - e2.source.1 as f64,
+    );
+    let offset = (e2.source.0 as f64 - p.0, e2.source.1 as f64 - p.1);
+
+    let t = (offset.0 * s.1 - offset.1 * s.0) / (r.0 * s.1 - r.1 * s.0);
+    (p.0 + r.0 * t, p.1 + r.1 * t)
+}
+
+struct Grid {
+    origin: (i64, i64),
+    cell_size: i64,
+    columns: i64,
+    rows: i64,
+}
+
+impl Grid {
+    /// Picks a cell size that gives us about as many cells as there are edges
+    fn new(edges: &[SimpleEdge]) -> Self {
+        let mut min = (i64::MAX, i64::MAX);
+        let mut max = (i64::MIN, i64::MIN);
+        for point in edges.iter().flat_map(|e| [e.source, e.target]) {
+            min = (min.0.min(point.0 as i64), min.1.min(point.1 as i64));
+            max = (max.0.max(point.0 as i64), max.1.max(point.1 as i64));
+        }
+
+        let width = (max.0 - min.0 + 1) as f64;
+        let height = (max.1 - min.1 + 1) as f64;
+        let cell_size = ((width * height / edges.len() as f64).sqrt().ceil() as i64).max(1);
+
+        Self {
+            origin: min,
+            cell_size,
+            columns: (max.0 - min.0) / cell_size + 1,
+            rows: (max.1 - min.1) / cell_size + 1,
+        }
+    }
+
+    fn cell_index(&self, column: i64, row: i64) -> usize {
+        (row * self.columns + column) as usize
+    }
+
+    /// Puts every edge into every cell that it touches
+    fn bucket_edges(&self, edges: &[SimpleEdge]) -> Vec<Vec<usize>> {
+        let mut cells = vec![vec![]; (self.columns * self.rows) as usize];
+        // Edges of length zero never cross anything. Skipping them matters, since unoptimized inputs
+        // often have all nodes at the same coordinates.
+        for (index, edge) in edges.iter().enumerate() {
+            if edge.source == edge.target {
+                continue;
+            }
+            self.for_each_cell(edge, |cell| cells[cell].push(index));
+        }
+        cells
+    }
+
+    /// Visits a superset of the cells that contain a point of the edge.
+    /// Points on a cell border belong to the cell to their top right.
+    fn for_each_cell(&self, edge: &SimpleEdge, mut visit: impl FnMut(usize)) {
+        let (mut x1, mut y1) = self.relative(edge.source);
+        let (mut x2, mut y2) = self.relative(edge.target);
+        if x1 > x2 {
+            std::mem::swap(&mut x1, &mut x2);
+            std::mem::swap(&mut y1, &mut y2);
+        }
+        let cs = self.cell_size;
+
+        if x1 == x2 {
+            let column = x1 / cs;
+            for row in (y1.min(y2) / cs)..=(y1.max(y2) / cs) {
+                visit(self.cell_index(column, row));
+            }
+            return;
+        }
+
+        // Row of the point on the edge with the given x-coordinate.
+        // y(x) = y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+        let dx = (x2 - x1) as i128;
+        let dy = (y2 - y1) as i128;
+        let row_at = |x: i64| {
+            let numerator = y1 as i128 * dx + dy * (x - x1) as i128;
+            numerator.div_euclid(dx * cs as i128) as i64
+        };
+
+        for column in (x1 / cs)..=(x2 / cs) {
+            let from = row_at(x1.max(column * cs));
+            let to = row_at(x2.min((column + 1) * cs));
+            for row in from.min(to)..=from.max(to) {
+                visit(self.cell_index(column, row));
+            }
+        }
+    }
+
+    /// The cell with the intersection point of two crossing edges, computed with exact integers
+    fn intersection_cell(&self, e1: &SimpleEdge, e2: &SimpleEdge) -> usize {
+        let p1 = self.relative(e1.source);
+        let q1 = self.relative(e1.target);
+        let p2 = self.relative(e2.source);
+        let q2 = self.relative(e2.target);
+
+        let r = ((q1.0 - p1.0) as i128, (q1.1 - p1.1) as i128);
+        let s = ((q2.0 - p2.0) as i128, (q2.1 - p2.1) as i128);
+        let offset = ((p2.0 - p1.0) as i128, (p2.1 - p1.1) as i128);
+
+        // Crossing edges are never parallel, so this is not zero
+        let mut denominator = r.0 * s.1 - r.1 * s.0;
+        let mut t = offset.0 * s.1 - offset.1 * s.0;
+        if denominator < 0 {
+            denominator = -denominator;
+            t = -t;
+        }
+
+        // intersection = p1 + r * t / denominator
+        let cs = self.cell_size as i128;
+        let column = (p1.0 as i128 * denominator + r.0 * t).div_euclid(denominator * cs);
+        let row = (p1.1 as i128 * denominator + r.1 * t).div_euclid(denominator * cs);
+        self.cell_index(column as i64, row as i64)
+    }
+
+    fn relative(&self, (x, y): (u32, u32)) -> (i64, i64) {
+        (x as i64 - self.origin.0, y as i64 - self.origin.1)
+    }
+}
+
+fn pair_count(n: usize) -> usize {
+    n * n.saturating_sub(1) / 2
+}
+
+fn bounding_boxes_overlap(e1: &SimpleEdge, e2: &SimpleEdge) -> bool {
+    let overlaps =
+        |a1: u32, a2: u32, b1: u32, b2: u32| a1.max(a2) >= b1.min(b2) && b1.max(b2) >= a1.min(a2);
+    overlaps(e1.source.0, e1.target.0, e2.source.0, e2.target.0)
+        && overlaps(e1.source.1, e1.target.1, e2.source.1, e2.target.1)
+}
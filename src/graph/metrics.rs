@@ -0,0 +1,136 @@
+//! Secondary quality metrics of a drawing, for deciding between drawings with the same crossing
+//! number.
+
+use std::cmp::Ordering;
+
+use super::{Graph, crossings};
+
+/// Measures how good a drawing is. The crossing number comes first, the rest are tie-breakers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityMetrics {
+    pub max_per_edge: u32,
+    pub total: u32,
+    /// How many edges have `max_per_edge` crossings. Zero if there are no crossings.
+    pub edges_at_max: u32,
+    /// Smallest angle between two crossing edges, in degrees. 90 if nothing crosses.
+    pub min_crossing_angle: f64,
+    /// Variance of the edge lengths, divided by the squared mean length.
+    /// This makes it independent of how large the drawing is.
+    pub edge_length_variance: f64,
+    /// How much of the `width` times `height` area the bounding box of the nodes covers
+    pub bounding_box_usage: f64,
+}
+
+/// A secondary metric for deciding between drawings with the same crossing number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreaker {
+    /// Fewer crossings in total
+    Total,
+    /// Fewer edges with the maximum number of crossings
+    EdgesAtMax,
+    /// A larger minimum crossing angle
+    CrossingAngle,
+    /// More uniform edge lengths
+    EdgeLengthVariance,
+    /// Using more of the drawing area
+    BoundingBox,
+}
+
+impl TieBreaker {
+    /// Returns `Less` if `a` is better than `b`
+    pub fn compare(self, a: &QualityMetrics, b: &QualityMetrics) -> Ordering {
+        match self {
+            TieBreaker::Total => a.total.cmp(&b.total),
+            TieBreaker::EdgesAtMax => a.edges_at_max.cmp(&b.edges_at_max),
+            TieBreaker::CrossingAngle => b.min_crossing_angle.total_cmp(&a.min_crossing_angle),
+            TieBreaker::EdgeLengthVariance => {
+                a.edge_length_variance.total_cmp(&b.edge_length_variance)
+            }
+            TieBreaker::BoundingBox => b.bounding_box_usage.total_cmp(&a.bounding_box_usage),
+        }
+    }
+}
+
+impl QualityMetrics {
+    /// Compares the crossing number first, and then the tie-breakers in order.
+    /// Returns `Less` if `self` is better than `other`.
+    pub fn compare(&self, other: &QualityMetrics, tie_breakers: &[TieBreaker]) -> Ordering {
+        tie_breakers.iter().fold(
+            self.max_per_edge.cmp(&other.max_per_edge),
+            |ordering, tie_breaker| ordering.then_with(|| tie_breaker.compare(self, other)),
+        )
+    }
+}
+
+impl Graph {
+    /// Computes the crossing numbers together with the tie-breaker metrics
+    pub fn quality_metrics(&self) -> QualityMetrics {
+        let edges = self.simple_edges();
+
+        let mut total = 0;
+        let mut per_edge = vec![0u32; edges.len()];
+        let mut min_crossing_angle = 90.0f64;
+        crossings::for_each_crossing(&edges, |i1, i2| {
+            per_edge[i1] += 1;
+            per_edge[i2] += 1;
+            total += 1;
+
+            let direction = |i: usize| {
+                let e = &edges[i];
+                (
+                    e.target.0 as f64 - e.source.0 as f64,
+                    e.target.1 as f64 - e.source.1 as f64,
+                )
+            };
+            let (u, v) = (direction(i1), direction(i2));
+            let cos = (u.0 * v.0 + u.1 * v.1).abs() / (u.0.hypot(u.1) * v.0.hypot(v.1));
+            min_crossing_angle = min_crossing_angle.min(cos.min(1.0).acos().to_degrees());
+        });
+
+        let max_per_edge = per_edge.iter().copied().max().unwrap_or_default();
+        let edges_at_max = if max_per_edge == 0 {
+            0
+        } else {
+            per_edge.iter().filter(|&&c| c == max_per_edge).count() as u32
+        };
+
+        let lengths = edges
+            .iter()
+            .map(|e| {
+                (e.target.0 as f64 - e.source.0 as f64).hypot(e.target.1 as f64 - e.source.1 as f64)
+            })
+            .collect::<Vec<_>>();
+        let mean = lengths.iter().sum::<f64>() / lengths.len().max(1) as f64;
+        let edge_length_variance = if mean > 0.0 {
+            lengths
+                .iter()
+                .map(|l| (l / mean - 1.0).powi(2))
+                .sum::<f64>()
+                / lengths.len() as f64
+        } else {
+            0.0
+        };
+
+        let area = self.width as f64 * self.height as f64;
+        let bounding_box_usage = match (
+            self.nodes.iter().map(|n| n.x).min(),
+            self.nodes.iter().map(|n| n.x).max(),
+            self.nodes.iter().map(|n| n.y).min(),
+            self.nodes.iter().map(|n| n.y).max(),
+        ) {
+            (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) if area > 0.0 => {
+                (max_x - min_x) as f64 * (max_y - min_y) as f64 / area
+            }
+            _ => 0.0,
+        };
+
+        QualityMetrics {
+            max_per_edge,
+            total,
+            edges_at_max,
+            min_crossing_angle,
+            edge_length_variance,
+            bounding_box_usage,
+        }
+    }
+}
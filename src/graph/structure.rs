@@ -0,0 +1,176 @@
+//! Checks whether an optimizer kept the structure of the input graph intact.
+
+use std::fmt;
+
+use petgraph::{algo::isomorphism::subgraph_isomorphisms_iter, graph::UnGraph};
+
+use super::{Edge, Graph, minmax};
+
+/// How the structure of two graphs differs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphMismatch {
+    /// Node IDs that only the expected graph has
+    pub missing_nodes: Vec<usize>,
+    /// Node IDs that only the actual graph has
+    pub unexpected_nodes: Vec<usize>,
+    /// Edges that only the expected graph has, with `source <= target`
+    pub missing_edges: Vec<Edge>,
+    /// Edges that only the actual graph has, with `source <= target`
+    pub unexpected_edges: Vec<Edge>,
+}
+
+impl fmt::Display for GraphMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SHOWN: usize = 5;
+        fn list<T: fmt::Debug>(items: &[T]) -> String {
+            let shown = items.iter().take(SHOWN).collect::<Vec<_>>();
+            if items.len() > SHOWN {
+                format!("{} (showing {shown:?})", items.len())
+            } else {
+                format!("{shown:?}")
+            }
+        }
+        fn edges(edges: &[Edge]) -> Vec<(usize, usize)> {
+            edges.iter().map(|e| (e.source, e.target)).collect()
+        }
+
+        write!(
+            f,
+            "missing nodes {}, unexpected nodes {}, missing edges {}, unexpected edges {}",
+            list(&self.missing_nodes),
+            list(&self.unexpected_nodes),
+            list(&edges(&self.missing_edges)),
+            list(&edges(&self.unexpected_edges)),
+        )
+    }
+}
+
+impl GraphMismatch {
+    /// Only the counts, for a short description
+    pub fn summary(&self) -> String {
+        format!(
+            "{} missing and {} unexpected nodes, {} missing and {} unexpected edges",
+            self.missing_nodes.len(),
+            self.unexpected_nodes.len(),
+            self.missing_edges.len(),
+            self.unexpected_edges.len(),
+        )
+    }
+}
+
+impl Graph {
+    /// Checks that both graphs have the same node IDs and the same undirected edges.
+    /// Edges that appear multiple times have to appear equally often in both graphs.
+    pub fn same_structure(&self, actual: &Graph) -> Result<(), GraphMismatch> {
+        let (missing_nodes, unexpected_nodes) =
+            multiset_difference(self.node_ids(), actual.node_ids());
+        let (missing_edges, unexpected_edges) =
+            multiset_difference(self.undirected_edges(), actual.undirected_edges());
+
+        if missing_nodes.is_empty()
+            && unexpected_nodes.is_empty()
+            && missing_edges.is_empty()
+            && unexpected_edges.is_empty()
+        {
+            Ok(())
+        } else {
+            Err(GraphMismatch {
+                missing_nodes,
+                unexpected_nodes,
+                missing_edges,
+                unexpected_edges,
+            })
+        }
+    }
+
+    /// Uses VF2 to find a relabeling of the nodes that turns this graph into the other one.
+    ///
+    /// Returns `relabeling[id] = other_id`. Both graphs need node IDs from `0` to `n - 1`.
+    pub fn find_isomorphism(&self, other: &Graph) -> Option<Vec<usize>> {
+        if self.nodes.len() != other.nodes.len() || self.edges.len() != other.edges.len() {
+            return None;
+        }
+        let expected_edges = self.undirected_edges();
+        let (g0, g1) = (self.to_petgraph()?, other.to_petgraph()?);
+        let (g0, g1) = (&g0, &g1);
+        let actual_edges = other.undirected_edges();
+
+        // VF2 does not handle multigraphs, so we double check the edge counts of every mapping
+        let (mut node_match, mut edge_match) = (|_: &(), _: &()| true, |_: &(), _: &()| true);
+        let mut mappings = subgraph_isomorphisms_iter(&g0, &g1, &mut node_match, &mut edge_match)?;
+        mappings.find(|relabeling| {
+            let mut relabeled = expected_edges
+                .iter()
+                .map(|e| {
+                    let [source, target] = minmax(relabeling[e.source], relabeling[e.target]);
+                    Edge { source, target }
+                })
+                .collect::<Vec<_>>();
+            relabeled.sort();
+            relabeled == actual_edges
+        })
+    }
+
+    fn node_ids(&self) -> Vec<usize> {
+        let mut ids = self.nodes.iter().map(|n| n.id).collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    /// Sorted edges, with `source <= target`
+    fn undirected_edges(&self) -> Vec<Edge> {
+        let mut edges = self
+            .edges
+            .iter()
+            .map(|e| {
+                let [source, target] = minmax(e.source, e.target);
+                Edge { source, target }
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+        edges
+    }
+
+    /// A simple graph where the node indices are the node IDs
+    fn to_petgraph(&self) -> Option<UnGraph<(), ()>> {
+        let n = self.nodes.len();
+        if self.node_ids() != (0..n).collect::<Vec<_>>() {
+            return None;
+        }
+        let mut edges = self.undirected_edges();
+        edges.dedup();
+        if edges.iter().any(|e| e.target >= n) {
+            return None;
+        }
+
+        let mut graph = UnGraph::with_capacity(n, edges.len());
+        for _ in 0..n {
+            graph.add_node(());
+        }
+        graph.extend_with_edges(edges.iter().map(|e| (e.source as u32, e.target as u32)));
+        Some(graph)
+    }
+}
+
+/// Returns the items that are only in `a`, and the items that are only in `b`.
+/// Both inputs must be sorted.
+fn multiset_difference<T: Ord>(a: Vec<T>, b: Vec<T>) -> (Vec<T>, Vec<T>) {
+    let (mut only_a, mut only_b) = (vec![], vec![]);
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => match x.cmp(y) {
+                std::cmp::Ordering::Less => only_a.extend(a.next()),
+                std::cmp::Ordering::Greater => only_b.extend(b.next()),
+                std::cmp::Ordering::Equal => {
+                    a.next();
+                    b.next();
+                }
+            },
+            (Some(_), None) => only_a.extend(a.next()),
+            (None, Some(_)) => only_b.extend(b.next()),
+            (None, None) => return (only_a, only_b),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use super::{Graph, is_on_segment, minmax};
+
+/// A single reason why a drawing is not valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphViolation {
+    NodeIdOutOfBounds {
+        node: usize,
+        max_id: usize,
+    },
+    DuplicateNodeId {
+        node: usize,
+    },
+    MissingNodeId {
+        node: usize,
+    },
+    OutOfBounds {
+        node: usize,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    OverlappingNodes {
+        first: usize,
+        second: usize,
+        x: u32,
+        y: u32,
+    },
+    EdgeEndpointOutOfBounds {
+        edge: usize,
+        node: usize,
+        max_id: usize,
+    },
+    NodeOnEdge {
+        node: usize,
+        x: u32,
+        y: u32,
+        edge: usize,
+        source: usize,
+        target: usize,
+    },
+    /// The graph has a set of points, and the node is not on any of them
+    NodeNotOnPoint {
+        node: usize,
+        x: u32,
+        y: u32,
+    },
+    /// Two collinear edges that share more than a single point
+    OverlappingEdges {
+        first: usize,
+        second: usize,
+        from: (u32, u32),
+        to: (u32, u32),
+    },
+}
+
+impl GraphViolation {
+    /// Short name of the violation, without any of the IDs or coordinates
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GraphViolation::NodeIdOutOfBounds { .. } => "node_id_out_of_bounds",
+            GraphViolation::DuplicateNodeId { .. } => "duplicate_node_id",
+            GraphViolation::MissingNodeId { .. } => "missing_node_id",
+            GraphViolation::OutOfBounds { .. } => "out_of_bounds",
+            GraphViolation::OverlappingNodes { .. } => "overlapping_nodes",
+            GraphViolation::EdgeEndpointOutOfBounds { .. } => "edge_endpoint_out_of_bounds",
+            GraphViolation::NodeOnEdge { .. } => "node_on_edge",
+            GraphViolation::NodeNotOnPoint { .. } => "node_not_on_point",
+            GraphViolation::OverlappingEdges { .. } => "overlapping_edges",
+        }
+    }
+}
+
+impl fmt::Display for GraphViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphViolation::NodeIdOutOfBounds { node, max_id } => {
+                write!(f, "Node ID {node} is out of bounds (0 to {max_id})")
+            }
+            GraphViolation::DuplicateNodeId { node } => {
+                write!(f, "Node {node} is defined more than once")
+            }
+            GraphViolation::MissingNodeId { node } => write!(f, "Node {node} is not defined"),
+            GraphViolation::OutOfBounds {
+                node,
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "Node {node} at ({x}, {y}) is outside of the drawing area {width}x{height}"
+            ),
+            GraphViolation::OverlappingNodes {
+                first,
+                second,
+                x,
+                y,
+            } => write!(
+                f,
+                "Node with ID {first} overlaps with node with ID {second} at coordinates ({x}, {y})"
+            ),
+            GraphViolation::EdgeEndpointOutOfBounds { edge, node, max_id } => write!(
+                f,
+                "Edge {edge} has the endpoint {node}, which is out of bounds (0 to {max_id})"
+            ),
+            GraphViolation::NodeOnEdge {
+                node,
+                x,
+                y,
+                edge,
+                source,
+                target,
+            } => write!(
+                f,
+                "Node {node} at coordinates ({x}, {y}) lies on edge {edge} from node {source} to node {target}"
+            ),
+            GraphViolation::NodeNotOnPoint { node, x, y } => {
+                write!(f, "Node {node} at ({x}, {y}) is not on one of the points")
+            }
+            GraphViolation::OverlappingEdges {
+                first,
+                second,
+                from,
+                to,
+            } => write!(
+                f,
+                "Edge {first} overlaps with edge {second} from ({}, {}) to ({}, {})",
+                from.0, from.1, to.0, to.1
+            ),
+        }
+    }
+}
+
+/// All the reasons why a drawing is not valid
+#[derive(Debug, Clone)]
+pub struct GraphValidationError {
+    pub violations: Vec<GraphViolation>,
+}
+
+impl fmt::Display for GraphValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} violation(s)", self.violations.len())?;
+        if let Some(first) = self.violations.first() {
+            write!(f, ", first one: {first}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GraphValidationError {}
+
+impl GraphValidationError {
+    /// The distinct kinds of violations, in the order in which they first occurred
+    pub fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds = vec![];
+        for violation in &self.violations {
+            if !kinds.contains(&violation.kind()) {
+                kinds.push(violation.kind());
+            }
+        }
+        kinds
+    }
+}
+
+impl Graph {
+    /// Checks the drawing, and collects every violation instead of stopping at the first one
+    pub fn is_valid(&self) -> Result<(), GraphValidationError> {
+        let num_nodes = self.nodes.len();
+        let max_id = num_nodes.saturating_sub(1);
+        let mut violations = vec![];
+
+        type NodeID = usize;
+        type Coordinate = (u32, u32);
+
+        let mut coordinates: HashMap<Coordinate, NodeID> = HashMap::with_capacity(num_nodes);
+
+        for node in &self.nodes {
+            if node.id >= num_nodes {
+                violations.push(GraphViolation::NodeIdOutOfBounds {
+                    node: node.id,
+                    max_id,
+                });
+            }
+            if node.x > self.width || node.y > self.height {
+                violations.push(GraphViolation::OutOfBounds {
+                    node: node.id,
+                    x: node.x,
+                    y: node.y,
+                    width: self.width,
+                    height: self.height,
+                });
+            }
+
+            if let Some(&duplicate_id) = coordinates.get(&(node.x, node.y)) {
+                violations.push(GraphViolation::OverlappingNodes {
+                    first: duplicate_id,
+                    second: node.id,
+                    x: node.x,
+                    y: node.y,
+                });
+            } else {
+                coordinates.insert((node.x, node.y), node.id);
+            }
+        }
+
+        // Nodes on the same point already got reported as overlapping
+        if !self.points.is_empty() {
+            let points = self
+                .points
+                .iter()
+                .map(|p| (p.x, p.y))
+                .collect::<HashSet<_>>();
+            for node in &self.nodes {
+                if !points.contains(&(node.x, node.y)) {
+                    violations.push(GraphViolation::NodeNotOnPoint {
+                        node: node.id,
+                        x: node.x,
+                        y: node.y,
+                    });
+                }
+            }
+        }
+
+        // To deal with a nodes array that is not sorted by the id
+        let mut id_to_idx = vec![None; num_nodes];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let Some(slot) = id_to_idx.get_mut(node.id) else {
+                continue; // Already reported as out of bounds
+            };
+            if slot.is_some() {
+                violations.push(GraphViolation::DuplicateNodeId { node: node.id });
+            } else {
+                *slot = Some(idx);
+            }
+        }
+
+        for (id, idx) in id_to_idx.iter().enumerate() {
+            if idx.is_none() {
+                violations.push(GraphViolation::MissingNodeId { node: id });
+            }
+        }
+
+        for (edge_idx, edge) in self.edges.iter().enumerate() {
+            let mut endpoints_defined = true;
+            for endpoint in [edge.source, edge.target] {
+                if endpoint >= num_nodes {
+                    violations.push(GraphViolation::EdgeEndpointOutOfBounds {
+                        edge: edge_idx,
+                        node: endpoint,
+                        max_id,
+                    });
+                    endpoints_defined = false;
+                }
+            }
+            if !endpoints_defined {
+                continue;
+            }
+
+            // Edges to nodes that are missing have already been reported
+            let (Some(source_idx), Some(target_idx)) =
+                (id_to_idx[edge.source], id_to_idx[edge.target])
+            else {
+                continue;
+            };
+            let from = &self.nodes[source_idx];
+            let to = &self.nodes[target_idx];
+
+            for node in &self.nodes {
+                if node.id == edge.source || node.id == edge.target {
+                    continue;
+                }
+
+                if is_on_segment((from.x, from.y), (node.x, node.y), (to.x, to.y)) {
+                    violations.push(GraphViolation::NodeOnEdge {
+                        node: node.id,
+                        x: node.x,
+                        y: node.y,
+                        edge: edge_idx,
+                        source: edge.source,
+                        target: edge.target,
+                    });
+                }
+            }
+        }
+
+        violations.extend(self.overlapping_edges(&id_to_idx));
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(GraphValidationError { violations })
+        }
+    }
+
+    /// Finds collinear edges that overlap, by grouping the edges by the line that they are on.
+    /// Edges between the same pair of nodes are skipped, since no drawing can avoid those.
+    fn overlapping_edges(&self, id_to_idx: &[Option<usize>]) -> Vec<GraphViolation> {
+        struct Segment {
+            edge: usize,
+            nodes: [usize; 2],
+            /// Positions along the line, with `start < end`
+            start: i64,
+            end: i64,
+            points: [(u32, u32); 2],
+        }
+
+        let mut lines: HashMap<(i64, i64, i64), Vec<Segment>> = HashMap::new();
+        for (edge_idx, edge) in self.edges.iter().enumerate() {
+            let (Some(Some(source_idx)), Some(Some(target_idx))) =
+                (id_to_idx.get(edge.source), id_to_idx.get(edge.target))
+            else {
+                continue;
+            };
+            let from = &self.nodes[*source_idx];
+            let to = &self.nodes[*target_idx];
+            let (mut p, mut q) = ((from.x, from.y), (to.x, to.y));
+            if p == q {
+                continue;
+            }
+
+            // The normalized direction and the offset uniquely identify the line
+            let (mut dx, mut dy) = (q.0 as i64 - p.0 as i64, q.1 as i64 - p.1 as i64);
+            let divisor = gcd(dx.abs(), dy.abs());
+            (dx, dy) = (dx / divisor, dy / divisor);
+            if dx < 0 || (dx == 0 && dy < 0) {
+                (dx, dy) = (-dx, -dy);
+                std::mem::swap(&mut p, &mut q);
+            }
+            let offset = dy * p.0 as i64 - dx * p.1 as i64;
+            let position = |(x, y): (u32, u32)| dx * x as i64 + dy * y as i64;
+
+            lines.entry((dx, dy, offset)).or_default().push(Segment {
+                edge: edge_idx,
+                nodes: minmax(edge.source, edge.target),
+                start: position(p),
+                end: position(q),
+                points: [p, q],
+            });
+        }
+
+        let mut overlaps = vec![];
+        for segments in lines.values_mut() {
+            segments.sort_by_key(|s| (s.start, s.edge));
+            let mut active: Vec<&Segment> = vec![];
+            for segment in segments.iter() {
+                active.retain(|a| a.end > segment.start);
+                for other in &active {
+                    if other.nodes == segment.nodes {
+                        continue;
+                    }
+                    let [first, second] = minmax(other.edge, segment.edge);
+                    let to = if other.end < segment.end {
+                        other.points[1]
+                    } else {
+                        segment.points[1]
+                    };
+                    overlaps.push((first, second, segment.points[0], to));
+                }
+                active.push(segment);
+            }
+        }
+        overlaps.sort();
+        overlaps
+            .into_iter()
+            .map(
+                |(first, second, from, to)| GraphViolation::OverlappingEdges {
+                    first,
+                    second,
+                    from,
+                    to,
+                },
+            )
+            .collect()
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
@@ -1,16 +1,27 @@
 use crate::{
-    graph::Graph,
-    leaderboard::stats::{GraphStats, ResultsWriter, RunStats},
-    optimizer_protocol::{LOG_INFO, Optimizer, OptimizerResponse},
+    graph::{Graph, GraphMismatch, GraphValidationError, QualityMetrics},
+    leaderboard::{
+        best,
+        stats::{
+            GraphStats, Outcome, ProgressStats, ProgressWriter, ResultsWriter, RunStats, read_runs,
+            results_path,
+        },
+    },
+    optimizer_protocol::{
+        GraphParams, LOG_ERROR, LOG_WARN, Optimizer, OptimizerInfo, OptimizerResponse,
+        PARAMS_CAPABILITY,
+    },
 };
 use smol::{
+    LocalExecutor,
+    channel::{self, Receiver, Sender},
     fs::{self, File, create_dir_all},
-    future,
     io::{self, AsyncWriteExt, BufWriter},
 };
 use std::{
+    collections::{BTreeMap, HashSet},
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 pub struct GraphsModeRunner {
@@ -18,14 +29,66 @@ pub struct GraphsModeRunner {
     pub filter: Option<String>,
     pub skip_to: Option<String>,
     pub save: bool,
+    /// Accept output graphs where the nodes got relabeled
+    pub isomorphism: bool,
+    /// Print which edges have the most crossings
+    pub explain: bool,
+    /// How long the optimizer may take for one graph
+    pub timeout: Option<Duration>,
+    /// How long to wait after the timeout before restarting the optimizer
+    pub grace: Duration,
+    /// Sent to optimizers that accept params
+    pub time_limit_ms: Option<u64>,
+    pub seed: Option<u64>,
+    /// How many optimizers run in parallel
+    pub jobs: usize,
+    /// Skip graphs that are already in the results file
+    pub resume: bool,
+    /// Don't skip graphs where the optimizer crashed or timed out
+    pub retry_failed: bool,
+    /// How often every graph is optimized, each time with a different seed
+    pub repeat: u32,
+}
+
+/// Everything that is the same for all graphs of a run
+struct RunContext {
+    info: OptimizerInfo,
+    /// The parameters that the optimizer announced, as JSON
+    info_params: String,
+    params: GraphParams,
+    graphs_count: usize,
+    jobs: usize,
+    repeat: u32,
+}
+
+/// One optimizer run on one graph
+struct GraphTask {
+    /// Position of the graph in the sorted list of all graphs
+    index: usize,
+    repetition: u32,
+    path: PathBuf,
+    name: String,
+}
+
+impl GraphTask {
+    /// Results are written in this order
+    fn key(&self) -> (usize, u32) {
+        (self.index, self.repetition)
+    }
+}
+
+/// What a worker found out about one graph
+struct GraphOutcome {
+    result: GraphStats,
+    progress: Vec<ProgressStats>,
 }
 
 impl GraphsModeRunner {
     /// Loads graphs from the filesystem
-    /// Starts optimizer
+    /// Starts optimizers
     /// Sends graphs, gets results
     /// Validates results
-    /// Restarts optimizer on crashes (goes to the next graph)
+    /// Restarts optimizers on crashes and timeouts (goes to the next graph)
     pub fn run(&self) -> impl Future<Output = io::Result<RunStats>> {
         println!("Starting {:?}", self.command);
         let graphs = collect_graphs(Path::new("./graphs"))
@@ -36,153 +99,701 @@ impl GraphsModeRunner {
         }
         let graphs_count = graphs.len();
 
-        let mut optimizer = Optimizer::new(&self.command, 1);
         let skip_to = self.skip_to.as_deref().unwrap_or_default();
+        let repeat = self.repeat;
+        let tasks = graphs
+            .into_iter()
+            .enumerate()
+            .skip_while(|(_, (_, name))| !name.contains(skip_to))
+            .flat_map(|(index, (path, name))| {
+                (0..repeat).map(move |repetition| GraphTask {
+                    index,
+                    repetition,
+                    path: path.clone(),
+                    name: name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
 
-        let (stderr_sender, stderr_receiver) =
-            smol::channel::bounded::<smol::process::ChildStderr>(2);
+        let params = GraphParams {
+            time_limit_ms: self
+                .time_limit_ms
+                .or(self.timeout.map(|v| v.as_millis() as u64)),
+            seed: Some(self.seed.unwrap_or_else(rand::random)),
+        };
+        let jobs = self.jobs;
 
-        let stderr_redirector = async move {
-            while let Ok(child_stderr) = stderr_receiver.recv().await {
-                let mut lines = io::AsyncBufReadExt::lines(io::BufReader::new(child_stderr));
-                while let Some(line) = smol::stream::StreamExt::next(&mut lines).await {
-                    eprintln!("{LOG_INFO}[Optimizer] {}{LOG_INFO:#}", line?);
+        async move {
+            let mut optimizers = vec![];
+            for worker_id in 1..=jobs {
+                let mut optimizer = Optimizer::new(&self.command, worker_id as u32);
+                smol::spawn(optimizer.redirect_stderr()).detach();
+                optimizers.push(optimizer);
+            }
+            let mut info: Option<OptimizerInfo> = None;
+            for optimizer in &mut optimizers {
+                let worker_info = optimizer.read_start().await?;
+                // All results are recorded under the name of the first worker
+                match &info {
+                    Some(info) => check_same_optimizer(info, &worker_info, optimizer.id())?,
+                    None => info = Some(worker_info),
                 }
             }
+            let info = info.expect("there is at least one worker");
+            let info_params = if info.params.is_empty() {
+                String::new()
+            } else {
+                serde_json::to_string(&info.params)?
+            };
+            println!("Optimizer {}", info.label());
+            if info.capabilities.iter().any(|v| v == PARAMS_CAPABILITY) {
+                println!("Sending params {}", serde_json::to_string(&params)?);
+            } else if repeat > 1 {
+                eprintln!(
+                    "{LOG_WARN}The optimizer doesn't accept params, so it gets no seeds and the repetitions only differ if it is random by itself{LOG_WARN:#}"
+                );
+            }
+            if jobs > 1 {
+                println!("Running {jobs} optimizers in parallel");
+            }
+            let tasks = if self.resume {
+                skip_recorded(tasks, &info, &info_params, self.retry_failed)?
+            } else {
+                tasks
+            };
 
-            io::Result::Ok(())
-        };
+            let order = tasks.iter().map(GraphTask::key).collect::<Vec<_>>();
+            let (queue_sender, queue) = channel::unbounded();
+            for task in tasks {
+                queue_sender
+                    .try_send(task)
+                    .expect("unbounded channel has space");
+            }
+            queue_sender.close();
 
-        let run_optimizer = async move {
-            stderr_sender.send(optimizer.take_stderr()).await.unwrap();
+            let context = RunContext {
+                info,
+                info_params,
+                params,
+                graphs_count,
+                jobs,
+                repeat,
+            };
+            let (outcome_sender, outcomes) = channel::unbounded();
+            let executor = LocalExecutor::new();
+            let workers = optimizers
+                .into_iter()
+                .map(|optimizer| {
+                    executor.spawn(self.work(
+                        optimizer,
+                        queue.clone(),
+                        outcome_sender.clone(),
+                        &context,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            std::mem::drop(outcome_sender);
 
-            let team_name = optimizer.read_start().await?;
-            let mut results_file = ResultsWriter::new(&team_name)?;
-            let mut runs = vec![];
+            let runs = executor
+                .run(async {
+                    let runs = write_outcomes(outcomes, &order, &context.info.name).await;
+                    for worker in workers {
+                        worker.await?;
+                    }
+                    runs
+                })
+                .await?;
 
-            for (graph_index, (graph_path, graph_name)) in graphs
-                .into_iter()
-                .enumerate()
-                .skip_while(|(_, (_, name))| !name.contains(&skip_to))
-            {
-                println!(
-                    "\nOptimizing {} ({graph_index}/{graphs_count} graphs)",
-                    graph_path.display(),
-                );
-                let graph_bytes = fs::read(graph_path)
-                    .await?
-                    .into_iter()
-                    .map(|v| if v == b'\n' { b' ' } else { v })
-                    .collect::<Vec<_>>();
+            Ok(RunStats {
+                name: context.info.label(),
+                runs,
+            })
+        }
+    }
+
+    /// Optimizes graphs from the queue until it is empty
+    async fn work(
+        &self,
+        mut optimizer: Optimizer,
+        queue: Receiver<GraphTask>,
+        outcomes: Sender<((usize, u32), GraphOutcome)>,
+        context: &RunContext,
+    ) -> io::Result<()> {
+        while let Ok(task) = queue.recv().await {
+            let outcome = self.optimize_graph(&mut optimizer, context, &task).await?;
+            if outcomes.send((task.key(), outcome)).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends one graph to the optimizer, and validates and scores the response
+    async fn optimize_graph(
+        &self,
+        optimizer: &mut Optimizer,
+        context: &RunContext,
+        task: &GraphTask,
+    ) -> io::Result<GraphOutcome> {
+        let graph_name = task.name.as_str();
+        let worker = if context.jobs > 1 {
+            format!(" [Optimizer {}]", optimizer.id())
+        } else {
+            String::new()
+        };
+        let repetition = if context.repeat > 1 {
+            format!(", repetition {}/{}", task.repetition + 1, context.repeat)
+        } else {
+            String::new()
+        };
+        println!(
+            "\nOptimizing {} ({}/{} graphs{repetition}){worker}",
+            task.path.display(),
+            task.index,
+            context.graphs_count,
+        );
+        let graph_bytes = fs::read(&task.path)
+            .await?
+            .into_iter()
+            .map(|v| if v == b'\n' { b' ' } else { v })
+            .collect::<Vec<_>>();
 
-                let input_graph: Graph = serde_json::from_slice(&graph_bytes)?;
+        let input_graph: Graph = serde_json::from_slice(&graph_bytes)?;
 
+        match optimizer.read_response().await? {
+            OptimizerResponse::GraphRequest => (),
+            // It crashed after its last graph, so this graph gets a fresh one
+            OptimizerResponse::NoResponse(exit_status) => {
+                eprintln!(
+                    "{LOG_ERROR}Optimizer exited before asking for {graph_name} ({}), restarting it{LOG_ERROR:#}",
+                    exit_status.map(|v| v.to_string()).unwrap_or_default()
+                );
+                let info = optimizer.relaunch().await?;
+                check_same_optimizer(&context.info, &info, optimizer.id())?;
                 optimizer.read_graph_request().await?;
+            }
+            response => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected graph request, but got {:?}", response),
+                ));
+            }
+        }
 
-                let start_time = Instant::now();
-                optimizer.write_graph_bytes(&graph_bytes).await?;
-
-                let (graph, mut result) = match optimizer.read_response().await? {
-                    OptimizerResponse::Graph { graph } => {
-                        let duration_ms = start_time.elapsed().as_millis() as u32;
-                        let max_per_edge = graph.crossings().max_per_edge;
-                        println!("Optimizer produced a graph with {max_per_edge} crossings");
-                        (
-                            graph,
-                            GraphStats {
-                                graph: graph_name.clone(),
-                                max_per_edge: Some(max_per_edge),
-                                duration_ms,
-                            },
-                        )
-                    }
-                    OptimizerResponse::NoResponse(exit_status) => {
-                        eprintln!("No graph was returned! Did the optimizer crash?");
-                        if let Some(exit_status) = exit_status {
-                            eprintln!("Exit status: {}", exit_status);
-                        }
-                        optimizer.restart().await?;
-                        stderr_sender.send(optimizer.take_stderr()).await.unwrap();
-                        _ = optimizer.read_start().await?;
-                        continue;
-                    }
-                    response => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            format!("expected graph, but got {:?}", response),
-                        ));
-                    }
-                };
+        // Every repetition gets its own seed
+        let params = GraphParams {
+            seed: context
+                .params
+                .seed
+                .map(|seed| seed.wrapping_add(task.repetition as u64)),
+            ..context.params.clone()
+        };
+        let sent_params = if optimizer.write_params(&params).await? {
+            params
+        } else {
+            GraphParams::default()
+        };
+        // The columns that every run has
+        let base_result = GraphStats {
+            graph: graph_name.to_string(),
+            repetition: task.repetition,
+            version: context.info.version.clone(),
+            params: context.info_params.clone(),
+            time_limit_ms: sent_params.time_limit_ms,
+            seed: sent_params.seed,
+            ..Default::default()
+        };
+        let start_time = Instant::now();
+        optimizer.write_graph_bytes(&graph_bytes).await?;
 
-                if let Err(e) = graph.is_valid() {
-                    result.max_per_edge = None;
-                    eprintln!("Graph {} was invalid! {}", result.graph, e);
-                }
+        let response = optimizer
+            .read_response_timeout(
+                self.timeout
+                    .map(|timeout| timeout.saturating_add(self.grace)),
+            )
+            .await?;
+        let Some(response) = response else {
+            let duration_ms = start_time.elapsed().as_millis() as u32;
+            eprintln!(
+                "{LOG_ERROR}Optimizer did not respond to {graph_name} within {:.1}s, restarting it{LOG_ERROR:#}",
+                self.timeout
+                    .unwrap_or_default()
+                    .saturating_add(self.grace)
+                    .as_secs_f64()
+            );
+            let info = optimizer.relaunch().await?;
+            check_same_optimizer(&context.info, &info, optimizer.id())?;
 
-                if input_graph.nodes.len() != graph.nodes.len() {
-                    result.max_per_edge = None;
-                    eprintln!(
-                        "Output graph doesn't have the same number of nodes! Input has {} nodes. Output has {} nodes.",
-                        input_graph.nodes.len(),
-                        graph.nodes.len(),
-                    );
-                }
+            let mut result = GraphStats {
+                duration_ms,
+                ..base_result
+            };
+            result.fail(
+                Outcome::Timeout,
+                format!("no response after {duration_ms} ms"),
+            );
+            return Ok(GraphOutcome {
+                result,
+                progress: vec![],
+            });
+        };
 
-                if input_graph.edges.len() != graph.edges.len() {
-                    result.max_per_edge = None;
+        let (graph, progress, mut result) = match response {
+            OptimizerResponse::Graph { graph, progress } => {
+                let elapsed = start_time.elapsed();
+                if self.timeout.is_some_and(|timeout| elapsed > timeout) {
                     eprintln!(
-                        "Output graph doesn't have the same number of edges! Input has {} edges. Output has {} edges",
-                        input_graph.edges.len(),
-                        graph.edges.len(),
+                        "{LOG_WARN}Optimizer took {:.1}s for {graph_name}, which is longer than the timeout{LOG_WARN:#}",
+                        elapsed.as_secs_f64()
                     );
                 }
+                let result = GraphStats {
+                    duration_ms: elapsed.as_millis() as u32,
+                    ..base_result
+                };
+                (graph, progress, result)
+            }
+            OptimizerResponse::Malformed { line, error } => {
+                const SHOWN_CHARS: usize = 200;
+                eprintln!(
+                    "{LOG_ERROR}Optimizer sent a malformed graph for {graph_name}: {error}{LOG_ERROR:#}"
+                );
+                let shown = line.chars().take(SHOWN_CHARS).collect::<String>();
+                eprintln!("{LOG_ERROR}    {shown}{LOG_ERROR:#}");
+                let mut result = GraphStats {
+                    duration_ms: start_time.elapsed().as_millis() as u32,
+                    ..base_result
+                };
+                result.fail(Outcome::Malformed, error);
+                return Ok(GraphOutcome {
+                    result,
+                    progress: vec![],
+                });
+            }
+            OptimizerResponse::NoResponse(exit_status) => {
+                eprintln!("No graph was returned for {graph_name}! Did the optimizer crash?");
+                let reason = match exit_status {
+                    Some(exit_status) => {
+                        eprintln!("Exit status: {}", exit_status);
+                        exit_status.to_string()
+                    }
+                    None => String::new(),
+                };
+                let mut result = GraphStats {
+                    duration_ms: start_time.elapsed().as_millis() as u32,
+                    ..base_result
+                };
+                result.fail(Outcome::Crashed, reason);
+                let info = optimizer.relaunch().await?;
+                check_same_optimizer(&context.info, &info, optimizer.id())?;
+                return Ok(GraphOutcome {
+                    result,
+                    progress: vec![],
+                });
+            }
+            response => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected graph, but got {:?}", response),
+                ));
+            }
+        };
 
-                if !input_graph.is_isomorphic(&graph) {
-                    eprintln!(
-                        "Warning: Output graph did not trivially match the input graph. Did the nodes get relabeled, or did something worse happen?",
-                    );
-                }
+        let (isomorphism, explain) = (self.isomorphism, self.explain);
+        let (graph, (result, progress)) =
+            score_in_background(input_graph, graph, move |input_graph, graph| {
+                score_drawing(input_graph, graph, &mut result, isomorphism, explain);
+                let progress = progress
+                    .into_iter()
+                    .map(|mut entry| {
+                        let scored = drawing_metrics(input_graph, &mut entry.graph, isomorphism);
+                        (entry.elapsed, scored.ok().map(|scored| scored.metrics))
+                    })
+                    .collect::<Vec<_>>();
+                (result, progress)
+            })
+            .await;
+        if let Some(max_per_edge) = result.max_per_edge {
+            println!("Optimizer produced a graph with {max_per_edge} crossings{worker}");
+        }
 
-                if self.save {
-                    let mut path = PathBuf::from("./saved");
-                    path.push(&team_name.trim_start_matches('/'));
-                    path.push(&graph_name.trim_start_matches('/'));
-                    path.set_extension("json");
+        let mut progress_stats = vec![];
+        if !progress.is_empty() {
+            for (elapsed, metrics) in progress {
+                progress_stats.push(ProgressStats {
+                    graph: graph_name.to_string(),
+                    elapsed_ms: elapsed.as_millis() as u32,
+                    max_per_edge: metrics.as_ref().map(|v| v.max_per_edge),
+                    total_crossings: metrics.as_ref().map(|v| v.total),
+                    is_final: false,
+                });
+            }
+            progress_stats.push(ProgressStats {
+                graph: graph_name.to_string(),
+                elapsed_ms: result.duration_ms,
+                max_per_edge: result.max_per_edge,
+                total_crossings: result.max_per_edge.and(result.total_crossings),
+                is_final: true,
+            });
+        }
 
-                    if let Some(parent) = path.parent() {
-                        create_dir_all(parent).await?;
-                    }
+        if self.save {
+            let mut path = PathBuf::from("./saved");
+            path.push(context.info.name.trim_start_matches('/'));
+            path.push(graph_name.trim_start_matches('/'));
+            if context.repeat > 1 {
+                // Keeps one drawing per repetition
+                path.set_extension(format!("{}.json", task.repetition));
+            } else {
+                path.set_extension("json");
+            }
 
-                    let file = File::create(&path).await?;
-                    let mut writer = BufWriter::new(file);
-                    let json_data = serde_json::to_vec(&graph)?;
-                    writer.write_all(&json_data).await?;
-                    writer.flush().await?;
-                    std::mem::drop(writer);
-                }
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).await?;
+            }
+
+            let file = File::create(&path).await?;
+            let mut writer = BufWriter::new(file);
+            let json_data = serde_json::to_vec(&graph)?;
+            writer.write_all(&json_data).await?;
+            writer.flush().await?;
+            std::mem::drop(writer);
+        }
 
-                results_file.write_single_run(&result)?;
+        best::update_record(&context.info, &result, graph).await?;
 
-                runs.push(result);
+        Ok(GraphOutcome {
+            result,
+            progress: progress_stats,
+        })
+    }
+}
+
+/// Fails if a worker announced a different name, version or parameters than the first one
+fn check_same_optimizer(
+    expected: &OptimizerInfo,
+    actual: &OptimizerInfo,
+    id: u32,
+) -> io::Result<()> {
+    if actual.label() != expected.label() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "optimizer {id} started as {:?}, but optimizer 1 as {:?}",
+                actual.label(),
+                expected.label()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Removes the graphs that already have a result from the same optimizer version and parameters
+fn skip_recorded(
+    tasks: Vec<GraphTask>,
+    info: &OptimizerInfo,
+    info_params: &str,
+    retry_failed: bool,
+) -> io::Result<Vec<GraphTask>> {
+    let previous_runs = match std::fs::File::open(results_path(&info.name)) {
+        Ok(file) => read_runs(file)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e),
+    };
+    let tasks_count = tasks.len();
+    let tasks = without_recorded(tasks, &previous_runs, info, info_params, retry_failed);
+    println!(
+        "Resuming, skipping {} runs that already have results",
+        tasks_count - tasks.len()
+    );
+    Ok(tasks)
+}
+
+fn without_recorded(
+    tasks: Vec<GraphTask>,
+    previous_runs: &[GraphStats],
+    info: &OptimizerInfo,
+    info_params: &str,
+    retry_failed: bool,
+) -> Vec<GraphTask> {
+    let recorded = previous_runs
+        .iter()
+        .filter(|run| run.version == info.version && run.params == info_params)
+        .filter(|run| !(retry_failed && run.is_failure()))
+        .map(|run| (run.graph.as_str(), run.repetition))
+        .collect::<HashSet<_>>();
+
+    tasks
+        .into_iter()
+        .filter(|task| !recorded.contains(&(task.name.as_str(), task.repetition)))
+        .collect()
+}
+
+/// Writes the outcomes in the order of the graphs, no matter which worker finished first
+async fn write_outcomes(
+    outcomes: Receiver<((usize, u32), GraphOutcome)>,
+    order: &[(usize, u32)],
+    team_name: &str,
+) -> io::Result<Vec<GraphStats>> {
+    let mut results_file = ResultsWriter::new(team_name)?;
+    // Only created for optimizers that send progress
+    let mut progress_file = None;
+    let mut runs = vec![];
+
+    in_order(outcomes, order, |outcome: GraphOutcome| {
+        if !outcome.progress.is_empty() {
+            let writer = match &mut progress_file {
+                Some(writer) => writer,
+                None => progress_file.insert(ProgressWriter::new(team_name)?),
+            };
+            for entry in &outcome.progress {
+                writer.write(entry)?;
             }
+            writer.flush()?;
+        }
+        results_file.write_single_run(&outcome.result)?;
+        runs.push(outcome.result);
+        Ok(())
+    })
+    .await?;
 
-            results_file.flush()?;
+    results_file.flush()?;
+    Ok(runs)
+}
 
-            io::Result::Ok(RunStats {
-                name: team_name,
-                runs,
-            })
+/// Hands the outcomes to `write` in the given order, as soon as all earlier ones are there
+async fn in_order<T>(
+    outcomes: Receiver<((usize, u32), T)>,
+    order: &[(usize, u32)],
+    mut write: impl FnMut(T) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    loop {
+        let done = match outcomes.recv().await {
+            Ok((key, outcome)) => {
+                pending.insert(key, outcome);
+                false
+            }
+            Err(_) => true,
         };
+        // After the last outcome, skip the gaps from workers that failed
+        while next < order.len() && (done || pending.contains_key(&order[next])) {
+            if let Some(outcome) = pending.remove(&order[next]) {
+                write(outcome)?;
+            }
+            next += 1;
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
 
-        async move {
-            let (runs, b) = future::zip(run_optimizer, stderr_redirector).await;
-            let (runs, _) = (runs?, b?);
-            Ok(runs)
+/// Runs `score` on the blocking thread pool, and hands the drawing back afterwards.
+/// Scoring takes a while on large graphs, and the other optimizers shouldn't wait for it.
+pub async fn score_in_background<T: Send + 'static>(
+    input_graph: Graph,
+    mut drawing: Graph,
+    score: impl FnOnce(&Graph, &mut Graph) -> T + Send + 'static,
+) -> (Graph, T) {
+    smol::unblock(move || {
+        let scored = score(&input_graph, &mut drawing);
+        (drawing, scored)
+    })
+    .await
+}
+
+/// Checks and scores the drawing, and records the metrics or why it was rejected
+fn score_drawing(
+    input_graph: &Graph,
+    graph: &mut Graph,
+    result: &mut GraphStats,
+    isomorphism: bool,
+    explain: bool,
+) {
+    match drawing_metrics(input_graph, graph, isomorphism) {
+        Ok(scored) => {
+            if let Some(relabeling) = &scored.relabeling {
+                println!(
+                    "Output graph has relabeled nodes (input -> output): {}",
+                    format_relabeling(relabeling)
+                );
+            }
+            result.set_metrics(&scored.metrics);
+            if explain {
+                print_crossing_report(graph);
+            }
+        }
+        Err(rejection) => {
+            result.fail(rejection.outcome(), rejection.reason());
+            match &rejection {
+                Rejection::Mismatch(mismatch) => {
+                    eprintln!("Output graph does not match the input graph! {mismatch}");
+                }
+                Rejection::Invalid(e) => {
+                    result.violations = e.kinds().join(";");
+                    print_violations(&result.graph, e);
+                }
+            }
+        }
+    }
+}
+
+/// A drawing that passed the checks
+pub struct ScoredDrawing {
+    pub metrics: QualityMetrics,
+    /// Where the input nodes ended up, if the optimizer relabeled them
+    pub relabeling: Option<Vec<usize>>,
+}
+
+/// Why a drawing can't be scored
+#[derive(Debug)]
+pub enum Rejection {
+    /// Missing nodes or made-up edges
+    Mismatch(GraphMismatch),
+    /// Broken rules, like nodes on top of each other
+    Invalid(GraphValidationError),
+}
+
+impl Rejection {
+    pub fn outcome(&self) -> Outcome {
+        match self {
+            Rejection::Mismatch(_) => Outcome::Mismatch,
+            Rejection::Invalid(_) => Outcome::Invalid,
+        }
+    }
+
+    /// A summary of the differences, or every violation
+    pub fn reason(&self) -> String {
+        match self {
+            Rejection::Mismatch(mismatch) => mismatch.summary(),
+            Rejection::Invalid(e) => {
+                let violations = e.violations.iter().map(|v| v.to_string());
+                violations.collect::<Vec<_>>().join("; ")
+            }
+        }
+    }
+}
+
+/// Checks that the drawing has the structure of the input graph and is valid, and only then scores it
+pub fn drawing_metrics(
+    input_graph: &Graph,
+    graph: &mut Graph,
+    isomorphism: bool,
+) -> Result<ScoredDrawing, Rejection> {
+    // The input decides where nodes may be placed, not the optimizer
+    graph.points = input_graph.points.clone();
+
+    let relabeling = match input_graph.same_structure(graph) {
+        Ok(()) => None,
+        Err(mismatch) => match isomorphism.then(|| input_graph.find_isomorphism(graph)) {
+            Some(Some(relabeling)) => Some(relabeling),
+            _ => return Err(Rejection::Mismatch(mismatch)),
+        },
+    };
+    graph.is_valid().map_err(Rejection::Invalid)?;
+    Ok(ScoredDrawing {
+        metrics: graph.quality_metrics(),
+        relabeling,
+    })
+}
+
+/// Prints the edges with the most crossings, and what they cross
+fn print_crossing_report(graph: &Graph) {
+    const SHOWN: usize = 10;
+    const SHOWN_CROSSINGS: usize = 20;
+    let report = graph.crossing_report();
+    println!(
+        "{} crossings in total, at most {} per edge",
+        report.total, report.max_per_edge
+    );
+
+    let mut edges_by_crossings = BTreeMap::new();
+    for &crossings in &report.per_edge {
+        *edges_by_crossings.entry(crossings).or_insert(0) += 1;
+    }
+    let histogram = edges_by_crossings
+        .iter()
+        .rev()
+        .take(SHOWN)
+        .map(|(crossings, edges)| format!("{crossings}: {edges}"))
+        .collect::<Vec<_>>();
+    println!("Edges per crossing count: {}", histogram.join(", "));
+
+    let describe_edge = |index: usize| {
+        let edge = &graph.edges[index];
+        format!("edge {index} ({} - {})", edge.source, edge.target)
+    };
+    for worst in report.worst_edges.iter().take(SHOWN) {
+        println!("Worst {} crosses", describe_edge(worst.edge));
+        for crossing in worst.crossings.iter().take(SHOWN_CROSSINGS) {
+            println!(
+                "    {} at ({:.1}, {:.1})",
+                describe_edge(crossing.edge),
+                crossing.point.0,
+                crossing.point.1
+            );
+        }
+        if worst.crossings.len() > SHOWN_CROSSINGS {
+            println!(
+                "    ... and {} more",
+                worst.crossings.len() - SHOWN_CROSSINGS
+            );
+        }
+    }
+    if report.worst_edges.len() > SHOWN {
+        println!(
+            "... and {} more edges with {} crossings",
+            report.worst_edges.len() - SHOWN,
+            report.max_per_edge
+        );
+    }
+}
+
+/// Lists the nodes that got a different ID
+fn format_relabeling(relabeling: &[usize]) -> String {
+    let changed = relabeling
+        .iter()
+        .enumerate()
+        .filter(|(from, to)| from != *to)
+        .map(|(from, to)| format!("{from} -> {to}"))
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        "none".to_string()
+    } else {
+        changed.join(", ")
+    }
+}
+
+/// Prints the violations grouped by their kind, with a few examples for each kind
+fn print_violations(graph_name: &str, error: &GraphValidationError) {
+    const EXAMPLES: usize = 3;
+    eprintln!(
+        "Graph {} was invalid! {} violation(s)",
+        graph_name,
+        error.violations.len()
+    );
+    for kind in error.kinds() {
+        let of_kind = error
+            .violations
+            .iter()
+            .filter(|v| v.kind() == kind)
+            .collect::<Vec<_>>();
+        eprintln!("  {kind}: {}", of_kind.len());
+        for violation in of_kind.iter().take(EXAMPLES) {
+            eprintln!("    {violation}");
+        }
+        if of_kind.len() > EXAMPLES {
+            eprintln!("    ... and {} more", of_kind.len() - EXAMPLES);
         }
     }
 }
 
-fn filter_graphs(graphs: Vec<(PathBuf, String)>, filter: Option<&str>) -> Vec<(PathBuf, String)> {
+pub fn filter_graphs(
+    graphs: Vec<(PathBuf, String)>,
+    filter: Option<&str>,
+) -> Vec<(PathBuf, String)> {
     if let Some(filter) = filter {
         graphs
             .into_iter()
@@ -194,7 +805,7 @@ fn filter_graphs(graphs: Vec<(PathBuf, String)>, filter: Option<&str>) -> Vec<(P
 }
 
 /// Collects all graphs for this run, and returns them in a sorted order
-fn collect_graphs(dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
+pub fn collect_graphs(dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
     fn collect_graphs_rec(
         dir: &Path,
         name: &str,
@@ -224,3 +835,112 @@ fn collect_graphs(dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
     graphs.sort(); // TODO: Use a number aware and case insensitive sorter here
     Ok(graphs)
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{GraphTask, drawing_metrics, in_order, score_drawing, without_recorded};
+    use crate::{
+        graph::Graph,
+        leaderboard::stats::{GraphStats, Outcome},
+        optimizer_protocol::OptimizerInfo,
+    };
+
+    #[test]
+    fn drawing_with_out_of_range_endpoint_is_not_scored() {
+        let input: Graph = serde_json::from_str(
+            r#"{"nodes":[{"id":0,"x":0,"y":0},{"id":1,"x":5,"y":5}],"edges":[{"source":0,"target":1}]}"#,
+        )
+        .unwrap();
+        let mut drawing: Graph = serde_json::from_str(
+            r#"{"nodes":[{"id":0,"x":0,"y":0},{"id":1,"x":5,"y":5}],"edges":[{"source":0,"target":7}]}"#,
+        )
+        .unwrap();
+
+        let mut result = GraphStats::default();
+        score_drawing(&input, &mut drawing, &mut result, true, true);
+        assert_ne!(result.outcome, Outcome::Ok);
+        assert_eq!(result.max_per_edge, None);
+        assert_eq!(result.total_crossings, None);
+    }
+
+    #[test]
+    fn drawing_metrics_reports_every_violation() {
+        let graph = r#"{"nodes":[{"id":0,"x":3,"y":3},{"id":1,"x":3,"y":3},{"id":2,"x":20,"y":3}],
+            "edges":[{"source":0,"target":1}],"width":10,"height":10}"#;
+        let input: Graph = serde_json::from_str(graph).unwrap();
+        let drawing: Graph = serde_json::from_str(graph).unwrap();
+        let expected = drawing.is_valid().unwrap_err().violations.len();
+        assert!(expected >= 2);
+
+        let mut drawing = drawing;
+        let rejection = drawing_metrics(&input, &mut drawing, false).err().unwrap();
+        assert_eq!(rejection.outcome(), Outcome::Invalid);
+        assert_eq!(rejection.reason().split("; ").count(), expected);
+
+        // Graphs mode records the same reason
+        let mut result = GraphStats::default();
+        score_drawing(&input, &mut drawing, &mut result, false, false);
+        assert_eq!(result.outcome, Outcome::Invalid);
+        assert_eq!(result.reason, rejection.reason());
+    }
+
+    #[test]
+    fn outcomes_are_written_in_task_order() {
+        let order = [(0, 0), (0, 1), (1, 0), (2, 0), (3, 0)];
+        let (sender, receiver) = smol::channel::unbounded();
+        // Two workers finished out of order, and the one with (2, 0) failed
+        for key in [(1, 0), (0, 1), (3, 0), (0, 0)] {
+            sender.try_send((key, key)).unwrap();
+        }
+        drop(sender);
+
+        let mut written = vec![];
+        smol::block_on(in_order(receiver, &order, |key| {
+            written.push(key);
+            Ok(())
+        }))
+        .unwrap();
+        assert_eq!(written, vec![(0, 0), (0, 1), (1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn resuming_skips_recorded_runs() {
+        let task = |index: usize, repetition: u32| GraphTask {
+            index,
+            repetition,
+            path: PathBuf::from(format!("./graphs/{index}.json")),
+            name: format!("/{index}.json"),
+        };
+        let run = |name: &str, repetition: u32, version: &str, outcome: Outcome| GraphStats {
+            graph: name.to_string(),
+            repetition,
+            version: version.to_string(),
+            outcome,
+            ..Default::default()
+        };
+        let previous_runs = [
+            run("/0.json", 0, "2", Outcome::Ok),
+            run("/1.json", 0, "2", Outcome::Timeout),
+            run("/2.json", 0, "1", Outcome::Ok),
+            run("/3.json", 1, "2", Outcome::Ok),
+        ];
+        let info = OptimizerInfo {
+            name: "opt".to_string(),
+            version: "2".to_string(),
+            ..Default::default()
+        };
+        let remaining = |retry_failed: bool| {
+            let tasks = vec![task(0, 0), task(1, 0), task(2, 0), task(3, 0), task(3, 1)];
+            without_recorded(tasks, &previous_runs, &info, "", retry_failed)
+                .iter()
+                .map(|task| task.key())
+                .collect::<Vec<_>>()
+        };
+
+        // Runs of other versions and repetitions don't count
+        assert_eq!(remaining(false), vec![(2, 0), (3, 0)]);
+        assert_eq!(remaining(true), vec![(1, 0), (2, 0), (3, 0)]);
+    }
+}
@@ -1,2 +1,3 @@
+pub mod best;
 pub mod plots;
 pub mod stats;
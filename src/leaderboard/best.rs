@@ -0,0 +1,286 @@
+//! The best known drawing of every graph, across all teams and runs, in `./best`
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::stats::{GraphStats, Outcome, RunStats};
+use crate::{
+    graph::Graph,
+    graphs_runner::collect_graphs,
+    optimizer_protocol::{LOG_WARN, OptimizerInfo, run_label},
+};
+
+/// Locks older than this were left behind by a crashed process
+const STALE_LOCK: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BestRecord {
+    /// Who found this drawing
+    pub team: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub params: String,
+    pub max_per_edge: u32,
+    pub total_crossings: u32,
+    /// When it was found, in seconds since the Unix epoch
+    pub timestamp: u64,
+    pub graph: Graph,
+}
+
+impl BestRecord {
+    /// Only the crossing number and then the total crossings count. The other tie-breakers are
+    /// chosen per leaderboard, but there is only one record per graph.
+    fn is_better_than(&self, other: &BestRecord) -> bool {
+        (self.max_per_edge, self.total_crossings) < (other.max_per_edge, other.total_crossings)
+    }
+}
+
+fn record_path(graph_name: &str) -> PathBuf {
+    let mut path = PathBuf::from("./best");
+    path.push(graph_name.trim_start_matches('/'));
+    path.set_extension("json");
+    path
+}
+
+/// Keeps other processes from updating the same record at the same time
+struct RecordLock(PathBuf);
+
+impl RecordLock {
+    /// Waits until no other process holds the lock of the record
+    fn acquire(record_path: &Path) -> io::Result<Self> {
+        let lock_path = record_path.with_extension("json.lock");
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(_) => return Ok(Self(lock_path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let is_stale = std::fs::metadata(&lock_path)
+                        .and_then(|v| v.modified())
+                        .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > STALE_LOCK);
+                    if is_stale {
+                        _ = std::fs::remove_file(&lock_path);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for RecordLock {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn read_record(path: &Path) -> io::Result<Option<BestRecord>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the record of a graph if this run is better. Only valid runs can become records.
+pub async fn update_record(
+    info: &OptimizerInfo,
+    result: &GraphStats,
+    graph: Graph,
+) -> io::Result<()> {
+    let (Outcome::Ok, Some(max_per_edge)) = (result.outcome, result.max_per_edge) else {
+        return Ok(());
+    };
+    let record = BestRecord {
+        team: info.name.clone(),
+        version: result.version.clone(),
+        params: result.params.clone(),
+        max_per_edge,
+        total_crossings: result.total_crossings.unwrap_or_default(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        graph,
+    };
+
+    let path = record_path(&result.graph);
+    // Waiting for the lock blocks, so the other workers keep running meanwhile
+    smol::unblock(move || replace_if_better(&path, record)).await?;
+    Ok(())
+}
+
+/// Returns whether the record was replaced
+fn replace_if_better(path: &Path, record: BestRecord) -> io::Result<bool> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Another process could write a better record between reading and replacing it
+    let _lock = RecordLock::acquire(path)?;
+    let previous = read_record(path).unwrap_or_else(|e| {
+        eprintln!(
+            "{LOG_WARN}Replacing unreadable record {}: {e}{LOG_WARN:#}",
+            path.display()
+        );
+        None
+    });
+    if let Some(previous) = &previous {
+        if !record.is_better_than(previous) {
+            return Ok(false);
+        }
+        println!(
+            "New best drawing with {} crossings, previously {} by {}",
+            record.max_per_edge, previous.max_per_edge, previous.team
+        );
+    }
+
+    // Writing to a temporary file and renaming it means that readers never see half a record
+    let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&temp_path, serde_json::to_vec(&record)?)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(true)
+}
+
+/// Prints every record, and how far each team is from it
+pub fn print_records(all_teams: &[RunStats]) -> io::Result<()> {
+    let mut graphs = match collect_graphs(Path::new("./best")) {
+        Ok(graphs) => graphs,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e),
+    };
+    // Skips the temporary and lock files of interrupted updates
+    graphs.retain(|(path, _)| path.extension().is_some_and(|v| v == "json"));
+    if graphs.is_empty() {
+        println!("No records yet. They are saved when running the graphs mode.");
+        return Ok(());
+    }
+
+    // The best valid crossing number of every team on every graph
+    let team_bests = all_teams
+        .iter()
+        .map(|team| {
+            let mut bests: HashMap<&str, u32> = HashMap::new();
+            for run in &team.runs {
+                if let Some(max_per_edge) = run.max_per_edge {
+                    bests
+                        .entry(run.graph.as_str())
+                        .and_modify(|v| *v = (*v).min(max_per_edge))
+                        .or_insert(max_per_edge);
+                }
+            }
+            bests
+        })
+        .collect::<Vec<_>>();
+    let mut total_gaps = vec![0; all_teams.len()];
+    let mut records_held = vec![0; all_teams.len()];
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for (path, graph_name) in graphs {
+        let Some(record) = read_record(&path)? else {
+            continue;
+        };
+        println!(
+            "{graph_name}: {} crossings by {} ({})",
+            record.max_per_edge,
+            run_label(&record.team, &record.version, &record.params),
+            format_age(now.saturating_sub(record.timestamp))
+        );
+        let gaps = all_teams
+            .iter()
+            .zip(&team_bests)
+            .enumerate()
+            .filter_map(|(team_id, (team, bests))| {
+                let best = *bests.get(graph_name.as_str())?;
+                // Negative for results from before the record was archived
+                let gap = best as i64 - record.max_per_edge as i64;
+                total_gaps[team_id] += gap.max(0);
+                if gap <= 0 {
+                    records_held[team_id] += 1;
+                }
+                Some(format!("{} {gap:+}", team.name))
+            })
+            .collect::<Vec<_>>();
+        if !gaps.is_empty() {
+            println!("    {}", gaps.join(", "));
+        }
+    }
+
+    println!("\nRecords matched, and the sum of the gaps to the records");
+    for (team_id, team) in all_teams.iter().enumerate() {
+        println!(
+            "{:>5} {:>8} {}",
+            records_held[team_id], total_gaps[team_id], team.name
+        );
+    }
+    Ok(())
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds} seconds ago"),
+        60..3600 => format!("{} minutes ago", seconds / 60),
+        3600..86400 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BestRecord, read_record, replace_if_better};
+
+    fn record(team: &str, max_per_edge: u32, total_crossings: u32) -> BestRecord {
+        BestRecord {
+            team: team.to_string(),
+            version: String::new(),
+            params: String::new(),
+            max_per_edge,
+            total_crossings,
+            timestamp: 0,
+            graph: serde_json::from_str(r#"{"nodes":[],"edges":[]}"#).unwrap(),
+        }
+    }
+
+    #[test]
+    fn records_compare_by_crossings() {
+        assert!(record("a", 2, 50).is_better_than(&record("b", 3, 10)));
+        assert!(record("a", 3, 9).is_better_than(&record("b", 3, 10)));
+        assert!(!record("a", 3, 10).is_better_than(&record("b", 3, 10)));
+        assert!(!record("a", 4, 0).is_better_than(&record("b", 3, 10)));
+    }
+
+    #[test]
+    fn only_better_records_replace_the_file() {
+        let dir = std::env::temp_dir().join(format!("gda_testing_best_{}", std::process::id()));
+        let path = dir.join("nested/graph.json");
+
+        assert!(replace_if_better(&path, record("first", 3, 10)).unwrap());
+        assert!(!replace_if_better(&path, record("tied", 3, 10)).unwrap());
+        assert!(!replace_if_better(&path, record("worse", 4, 0)).unwrap());
+        assert_eq!(read_record(&path).unwrap().unwrap().team, "first");
+        assert!(replace_if_better(&path, record("better", 3, 9)).unwrap());
+        assert_eq!(read_record(&path).unwrap().unwrap().team, "better");
+
+        // Neither the lock nor the temporary file are left behind
+        let files = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec!["graph.json"]);
+    }
+}
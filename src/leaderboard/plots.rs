@@ -1,21 +1,25 @@
-use super::stats::RunStats;
+use super::stats::{GraphStats, Outcome, ProgressStats, RunStats};
+use crate::graph::TieBreaker;
 use charming::{
     Chart, HtmlRenderer,
     component::{Axis, Feature, Legend, Toolbox, ToolboxDataZoom},
     datatype::{CompositeValue, DataPoint},
     element::{AxisType, Formatter, Tooltip},
-    series::Scatter,
+    series::{Line, Scatter},
 };
 use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 
-pub fn plot_leaderboard(all_teams: Vec<RunStats>) -> std::io::Result<()> {
+pub fn plot_leaderboard(
+    all_teams: Vec<RunStats>,
+    tie_breakers: &[TieBreaker],
+) -> std::io::Result<()> {
     let graph_names = get_graph_names(&all_teams);
     let graph_ids = make_graph_ids(&graph_names);
-    let best_crossing_values: Vec<u32> = get_best_crossing_values(&all_teams, &graph_ids)
-        .into_iter()
-        .map(|v| v.unwrap())
-        .collect();
+    print_graphs_won(&all_teams, &graph_ids, tie_breakers);
+    print_outcomes(&all_teams);
+    // Graphs where every run was invalid don't have a best value
+    let best_crossing_values = get_best_crossing_values(&all_teams, &graph_ids);
 
     let team_names = all_teams.iter().map(|v| v.name.clone()).collect::<Vec<_>>();
     let mut chart = Chart::new()
@@ -26,7 +30,9 @@ pub fn plot_leaderboard(all_teams: Vec<RunStats>) -> std::io::Result<()> {
             let score = params.data[1].toLocaleString(undefined, { minimumFractionDigits: 2 });
             let crossings = params.data[2];
             let graph = params.data[3];
-            return `${crossings} crossings on ${graph}<br>(score ${score})`;
+            let [median, spread, runs] = params.data.slice(4);
+            let repetitions = runs > 1 ? `<br>median ${median}, spread ${spread} over ${runs} runs` : '';
+            return `${crossings} crossings on ${graph}<br>(score ${score})${repetitions}`;
           }"
                 .into(),
             )),
@@ -49,20 +55,23 @@ pub fn plot_leaderboard(all_teams: Vec<RunStats>) -> std::io::Result<()> {
     for (team_id, team) in all_teams.iter().enumerate() {
         let best_crossing_values = &best_crossing_values;
         let graph_names = &graph_names;
-        let crossing_values = get_best_crossing_values(std::iter::once(team), &graph_ids);
-        println!("{:?}", crossing_values);
-        let scores = crossing_values
-            .iter()
+        let summaries = get_crossing_summaries(team, &graph_ids);
+        let scores = summaries
+            .into_iter()
             .enumerate()
             .filter_map(|(graph_id, v)| v.map(move |v| (graph_id, v)))
-            .map(move |(graph_id, v)| {
-                let relative_score = (best_crossing_values[graph_id] as f32) / (v as f32);
+            .map(move |(graph_id, summary)| {
+                let best = best_crossing_values[graph_id].expect("this team has a valid run");
+                let relative_score = relative_score(best, summary.best);
                 DataPoint::Value(CompositeValue::Array(vec![
                     CompositeValue::from(team_id as f64 + 0.5 + random_scatter(graph_id) * 0.1),
                     CompositeValue::from(relative_score),
                     // And here we store some extra data for hover texts
-                    CompositeValue::from(v as i64),
+                    CompositeValue::from(summary.best as i64),
                     CompositeValue::from(graph_names[graph_id].clone()),
+                    CompositeValue::from(summary.median),
+                    CompositeValue::from(summary.spread as i64),
+                    CompositeValue::from(summary.runs as i64),
                 ]))
             });
         data.extend(scores);
@@ -76,6 +85,103 @@ pub fn plot_leaderboard(all_teams: Vec<RunStats>) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Plots how the average relative score of each team develops over time
+pub fn plot_convergence(all_progress: &[(String, Vec<ProgressStats>)]) -> std::io::Result<()> {
+    let best_values = get_best_progress_values(all_progress);
+    let max_elapsed_ms = all_progress
+        .iter()
+        .flat_map(|(_, p)| p)
+        .map(|p| p.elapsed_ms)
+        .max()
+        .unwrap_or_default();
+    // Logarithmically spaced, ten per order of magnitude
+    let checkpoints_ms = (0..)
+        .map(|i| 10f64.powf(i as f64 / 10.0))
+        .take_while(|&t| t < max_elapsed_ms as f64 * 1.26)
+        .collect::<Vec<_>>();
+
+    let mut chart = Chart::new()
+        .legend(Legend::new())
+        .tooltip(Tooltip::new())
+        .x_axis(Axis::new().type_(AxisType::Log).name("Time (ms)"))
+        .y_axis(Axis::new().min(0.0).max(1.0).name("Average relative score"));
+
+    for (team, progress) in all_progress {
+        let data = checkpoints_ms
+            .iter()
+            .zip(convergence_curve(progress, &best_values, &checkpoints_ms))
+            .map(|(&t, average)| {
+                DataPoint::Value(CompositeValue::Array(vec![
+                    CompositeValue::from(t),
+                    CompositeValue::from(average),
+                ]))
+            })
+            .collect::<Vec<_>>();
+        chart = chart.series(Line::new().name(team.clone()).show_symbol(false).data(data));
+    }
+
+    let mut renderer = HtmlRenderer::new("Convergence", 1000, 760);
+    renderer
+        .save(&chart, "./convergence.html")
+        .expect("chart should be saved to ./convergence.html");
+    println!("Generated convergence curves. Open ./convergence.html in a browser!");
+    Ok(())
+}
+
+/// Best crossing number that anyone found for a graph, at any point in time
+fn get_best_progress_values(all_progress: &[(String, Vec<ProgressStats>)]) -> HashMap<&str, u32> {
+    let mut best_values: HashMap<&str, u32> = HashMap::new();
+    for progress in all_progress.iter().flat_map(|(_, p)| p) {
+        if let Some(max_per_edge) = progress.max_per_edge {
+            best_values
+                .entry(&progress.graph)
+                .and_modify(|v| *v = (*v).min(max_per_edge))
+                .or_insert(max_per_edge);
+        }
+    }
+    best_values
+}
+
+/// The average relative score of a team at every checkpoint.
+/// Graphs without a valid solution at that time count as 0.
+fn convergence_curve(
+    progress: &[ProgressStats],
+    best_values: &HashMap<&str, u32>,
+    checkpoints_ms: &[f64],
+) -> Vec<f64> {
+    let mut by_graph: HashMap<&str, Vec<&ProgressStats>> = HashMap::new();
+    for entry in progress {
+        by_graph.entry(&entry.graph).or_default().push(entry);
+    }
+    checkpoints_ms
+        .iter()
+        .map(|&t| {
+            let score_sum: f64 = by_graph
+                .iter()
+                .filter_map(|(graph, entries)| {
+                    let current = entries
+                        .iter()
+                        .filter(|p| p.elapsed_ms as f64 <= t)
+                        .filter_map(|p| p.max_per_edge)
+                        .min()?;
+                    Some(relative_score(best_values[graph], current))
+                })
+                .sum();
+            score_sum / by_graph.len().max(1) as f64
+        })
+        .collect()
+}
+
+/// The best known crossing number divided by the crossing number, so 1 for the best drawings.
+/// A crossing number of 0 is always the best.
+fn relative_score(best: u32, current: u32) -> f64 {
+    if current == 0 {
+        1.0
+    } else {
+        best as f64 / current as f64
+    }
+}
+
 fn get_graph_names(all_runs: &[RunStats]) -> Vec<String> {
     let mut graph_names = all_runs
         .iter()
@@ -106,6 +212,115 @@ fn make_graph_ids(graph_names: &[String]) -> HashMap<String, usize> {
         .collect()
 }
 
+/// Prints how often each team had the best drawing of a graph.
+/// Teams with equally good drawings all get the win.
+fn print_graphs_won(
+    all_teams: &[RunStats],
+    graph_ids: &HashMap<String, usize>,
+    tie_breakers: &[TieBreaker],
+) {
+    let best_runs = all_teams
+        .iter()
+        .map(|team| get_best_runs(team, graph_ids, tie_breakers))
+        .collect::<Vec<_>>();
+
+    let mut graphs_won = vec![0; all_teams.len()];
+    for graph_id in 0..graph_ids.len() {
+        let Some(best) = best_runs
+            .iter()
+            .filter_map(|runs| runs[graph_id])
+            .min_by(|a, b| a.compare(b, tie_breakers))
+        else {
+            continue;
+        };
+        for (team_id, runs) in best_runs.iter().enumerate() {
+            if runs[graph_id].is_some_and(|run| run.compare(best, tie_breakers).is_eq()) {
+                graphs_won[team_id] += 1;
+            }
+        }
+    }
+
+    let mut ranking = all_teams.iter().zip(graphs_won).collect::<Vec<_>>();
+    ranking.sort_by_key(|&(_, won)| std::cmp::Reverse(won));
+    println!("Graphs won (tie-breakers: {:?})", tie_breakers);
+    for (team, won) in ranking {
+        println!("{won:>5} {}", team.name);
+    }
+}
+
+/// Prints how many runs of each team failed, and how
+fn print_outcomes(all_teams: &[RunStats]) {
+    let header = Outcome::ALL
+        .iter()
+        .map(|outcome| format!("{:>10}", outcome.as_str()))
+        .collect::<String>();
+    println!("Outcomes\n{header}");
+    for team in all_teams {
+        let counts = team.outcome_counts();
+        let row = Outcome::ALL
+            .iter()
+            .map(|outcome| format!("{:>10}", counts.get(outcome).unwrap_or(&0)))
+            .collect::<String>();
+        println!("{row} {}", team.name);
+    }
+}
+
+/// The best valid run of a team for every graph
+fn get_best_runs<'a>(
+    team: &'a RunStats,
+    graph_ids: &HashMap<String, usize>,
+    tie_breakers: &[TieBreaker],
+) -> Vec<Option<&'a GraphStats>> {
+    let mut best_runs: Vec<Option<&GraphStats>> = vec![None; graph_ids.len()];
+    for run in team.runs.iter().filter(|run| run.max_per_edge.is_some()) {
+        let best = &mut best_runs[graph_ids[&run.graph]];
+        if best.is_none_or(|best| run.compare(best, tie_breakers).is_lt()) {
+            *best = Some(run);
+        }
+    }
+    best_runs
+}
+
+/// The crossing numbers of all valid runs of a team on one graph
+struct CrossingSummary {
+    best: u32,
+    median: f64,
+    /// Difference between the worst and the best run
+    spread: u32,
+    runs: usize,
+}
+
+fn get_crossing_summaries(
+    team: &RunStats,
+    graph_ids: &HashMap<String, usize>,
+) -> Vec<Option<CrossingSummary>> {
+    let mut values: Vec<Vec<u32>> = vec![vec![]; graph_ids.len()];
+    for run in &team.runs {
+        if let Some(max_per_edge) = run.max_per_edge {
+            values[graph_ids[&run.graph]].push(max_per_edge);
+        }
+    }
+    values
+        .into_iter()
+        .map(|mut values| {
+            values.sort_unstable();
+            let (&best, &worst) = (values.first()?, values.last()?);
+            let middle = values.len() / 2;
+            let median = if values.len() % 2 == 0 {
+                (values[middle - 1] + values[middle]) as f64 / 2.0
+            } else {
+                values[middle] as f64
+            };
+            Some(CrossingSummary {
+                best,
+                median,
+                spread: worst - best,
+                runs: values.len(),
+            })
+        })
+        .collect()
+}
+
 fn get_best_crossing_values<'a>(
     all_runs: impl IntoIterator<Item = &'a RunStats>,
     graph_ids: &HashMap<String, usize>,
@@ -113,7 +328,7 @@ fn get_best_crossing_values<'a>(
     let mut best_values: Vec<Option<u32>> = vec![None; graph_ids.len()];
     for (id, max_per_edge) in all_runs.into_iter().flat_map(|r| &r.runs).filter_map(|v| {
         v.max_per_edge
-            .map(|max_per_edge| (graph_ids[&v.graph] as usize, max_per_edge))
+            .map(|max_per_edge| (graph_ids[&v.graph], max_per_edge))
     }) {
         if let Some(v) = &mut best_values[id] {
             *v = (*v).min(max_per_edge);
@@ -123,3 +338,96 @@ fn get_best_crossing_values<'a>(
     }
     best_values
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        GraphStats, ProgressStats, RunStats, convergence_curve, get_best_progress_values,
+        get_crossing_summaries, relative_score,
+    };
+
+    fn progress(graph: &str, elapsed_ms: u32, max_per_edge: Option<u32>) -> ProgressStats {
+        ProgressStats {
+            graph: graph.to_string(),
+            elapsed_ms,
+            max_per_edge,
+            total_crossings: max_per_edge,
+            is_final: false,
+        }
+    }
+
+    #[test]
+    fn relative_scores() {
+        assert_eq!(relative_score(2, 4), 0.5);
+        assert_eq!(relative_score(3, 3), 1.0);
+        // Planar graphs and trees have a best crossing number of 0
+        assert_eq!(relative_score(0, 0), 1.0);
+        assert_eq!(relative_score(0, 2), 0.0);
+    }
+
+    #[test]
+    fn convergence_curves() {
+        let all_progress = vec![
+            (
+                "planar".to_string(),
+                vec![
+                    progress("a", 5, None),
+                    progress("a", 100, Some(0)),
+                    progress("b", 50, Some(3)),
+                ],
+            ),
+            ("slow".to_string(), vec![progress("a", 10, Some(2))]),
+        ];
+        let best_values = get_best_progress_values(&all_progress);
+        assert_eq!(best_values["a"], 0);
+        assert_eq!(best_values["b"], 3);
+
+        let checkpoints_ms = [10.0, 60.0, 200.0];
+        assert_eq!(
+            convergence_curve(&all_progress[0].1, &best_values, &checkpoints_ms),
+            vec![0.0, 0.5, 1.0]
+        );
+        assert_eq!(
+            convergence_curve(&all_progress[1].1, &best_values, &checkpoints_ms),
+            vec![0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn crossing_summaries() {
+        let run = |graph: &str, max_per_edge: Option<u32>| GraphStats {
+            graph: graph.to_string(),
+            max_per_edge,
+            ..Default::default()
+        };
+        let team = RunStats {
+            name: "team".to_string(),
+            runs: vec![
+                run("/a.json", Some(5)),
+                run("/a.json", Some(2)),
+                run("/a.json", Some(9)),
+                run("/b.json", Some(4)),
+                run("/b.json", None),
+                run("/b.json", Some(7)),
+                run("/c.json", None),
+            ],
+        };
+        let graph_ids = HashMap::from([
+            ("/a.json".to_string(), 0),
+            ("/b.json".to_string(), 1),
+            ("/c.json".to_string(), 2),
+        ]);
+        let summaries = get_crossing_summaries(&team, &graph_ids)
+            .into_iter()
+            .map(|summary| summary.map(|v| (v.best, v.median, v.spread, v.runs)))
+            .collect::<Vec<_>>();
+
+        // Invalid runs are left out, and graphs without a valid run have no summary
+        assert_eq!(
+            summaries,
+            vec![Some((2, 5.0, 7, 3)), Some((4, 5.5, 3, 2)), None]
+        );
+    }
+}
@@ -1,7 +1,13 @@
+use crate::{
+    graph::{QualityMetrics, TieBreaker},
+    optimizer_protocol::run_label,
+};
 use serde::{Deserialize, Serialize};
 use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
     fs::{File, OpenOptions},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -11,23 +17,173 @@ pub struct RunStats {
     pub runs: Vec<GraphStats>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GraphStats {
     /// Name of the graph
     pub graph: String,
     /// Crossing number. Empty if it was invalid
     pub max_per_edge: Option<u32>,
+    /// Whether the run succeeded, and how it failed otherwise
+    #[serde(default)]
+    pub outcome: Outcome,
+    /// A short explanation for failures
+    #[serde(default)]
+    pub reason: String,
+    /// Counts the runs of the same graph with different seeds
+    #[serde(default)]
+    pub repetition: u32,
     /// How long this run took
     pub duration_ms: u32,
+    /// Kinds of validation violations, separated by `;`. Empty if it was valid
+    #[serde(default)]
+    pub violations: String,
+    /// Version and parameters (as JSON) that the optimizer announced
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub params: String,
+    /// Time budget and seed that were sent to the optimizer, if it accepts params
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Tie-breaker metrics, see [`QualityMetrics`]
+    #[serde(default)]
+    pub total_crossings: Option<u32>,
+    #[serde(default)]
+    pub edges_at_max: Option<u32>,
+    #[serde(default)]
+    pub min_crossing_angle: Option<f64>,
+    #[serde(default)]
+    pub edge_length_variance: Option<f64>,
+    #[serde(default)]
+    pub bounding_box_usage: Option<f64>,
+}
+
+/// How a run ended
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// A valid drawing of the input graph
+    #[default]
+    Ok,
+    /// The drawing broke the rules, see the violations
+    Invalid,
+    /// The optimizer exited without responding
+    Crashed,
+    /// The optimizer was killed, because it didn't respond in time
+    Timeout,
+    /// The response could not be parsed
+    Malformed,
+    /// The drawing has different nodes or edges than the input graph
+    Mismatch,
+}
+
+impl Outcome {
+    pub const ALL: [Outcome; 6] = [
+        Outcome::Ok,
+        Outcome::Invalid,
+        Outcome::Crashed,
+        Outcome::Timeout,
+        Outcome::Malformed,
+        Outcome::Mismatch,
+    ];
+
+    /// The name in the CSV files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Invalid => "invalid",
+            Outcome::Crashed => "crashed",
+            Outcome::Timeout => "timeout",
+            Outcome::Malformed => "malformed",
+            Outcome::Mismatch => "mismatch",
+        }
+    }
+}
+
+impl GraphStats {
+    /// The optimizer never gave us a graph
+    pub fn is_failure(&self) -> bool {
+        matches!(self.outcome, Outcome::Crashed | Outcome::Timeout)
+    }
+
+    /// Marks the run as failed
+    pub fn fail(&mut self, outcome: Outcome, reason: impl Into<String>) {
+        self.max_per_edge = None;
+        self.outcome = outcome;
+        self.reason = reason.into();
+    }
+
+    pub fn set_metrics(&mut self, metrics: &QualityMetrics) {
+        self.max_per_edge = Some(metrics.max_per_edge);
+        self.total_crossings = Some(metrics.total);
+        self.edges_at_max = Some(metrics.edges_at_max);
+        self.min_crossing_angle = Some(metrics.min_crossing_angle);
+        self.edge_length_variance = Some(metrics.edge_length_variance);
+        self.bounding_box_usage = Some(metrics.bounding_box_usage);
+    }
+
+    /// The metrics of a valid run. Runs from before the tie-breakers existed don't have them.
+    pub fn metrics(&self) -> Option<QualityMetrics> {
+        Some(QualityMetrics {
+            max_per_edge: self.max_per_edge?,
+            total: self.total_crossings?,
+            edges_at_max: self.edges_at_max?,
+            min_crossing_angle: self.min_crossing_angle?,
+            edge_length_variance: self.edge_length_variance?,
+            bounding_box_usage: self.bounding_box_usage?,
+        })
+    }
+
+    /// Returns `Less` if this run is better than the other one. Runs without a result come last.
+    /// Falls back to only comparing the crossing numbers for runs without the tie-breaker metrics.
+    pub fn compare(&self, other: &GraphStats, tie_breakers: &[TieBreaker]) -> Ordering {
+        match (self.metrics(), other.metrics()) {
+            (Some(a), Some(b)) => a.compare(&b, tie_breakers),
+            _ => match (self.max_per_edge, other.max_per_edge) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+}
+
+/// Quality of an intermediate solution, for convergence curves
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ProgressStats {
+    /// Name of the graph
+    pub graph: String,
+    /// When the optimizer sent this solution
+    pub elapsed_ms: u32,
+    /// Crossing number. Empty if it was invalid
+    pub max_per_edge: Option<u32>,
+    pub total_crossings: Option<u32>,
+    /// Whether this was the final graph
+    pub is_final: bool,
 }
 
 pub struct ResultsWriter(csv::Writer<File>);
 
+impl RunStats {
+    /// How many runs ended with each outcome
+    pub fn outcome_counts(&self) -> BTreeMap<Outcome, usize> {
+        let mut counts = BTreeMap::new();
+        for run in &self.runs {
+            *counts.entry(run.outcome).or_default() += 1;
+        }
+        counts
+    }
+}
+
 impl ResultsWriter {
     pub fn new(name: &str) -> std::io::Result<Self> {
-        let mut path = PathBuf::from("./stats");
-        path.push(name);
-        path.set_extension("csv");
+        let path = results_path(name);
+        migrate_results_file(&path)?;
 
         let file = OpenOptions::new()
             .read(true)
@@ -51,43 +207,212 @@ impl ResultsWriter {
     }
 
     pub fn flush(&mut self) -> std::io::Result<()> {
-        Ok(self.0.flush()?)
+        self.0.flush()
+    }
+}
+
+/// Appends to `./stats/progress/<name>.csv`
+pub struct ProgressWriter(csv::Writer<File>);
+
+impl ProgressWriter {
+    pub fn new(name: &str) -> std::io::Result<Self> {
+        let mut path = PathBuf::from("./stats/progress");
+        std::fs::create_dir_all(&path)?;
+        path.push(name);
+        path.set_extension("csv");
+
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let file_size = file.metadata().map(|v| v.len()).unwrap_or_default();
+        let writer = csv::WriterBuilder::new()
+            .has_headers(file_size == 0)
+            .from_writer(file);
+        Ok(Self(writer))
+    }
+
+    pub fn write(&mut self, progress: &ProgressStats) -> std::io::Result<()> {
+        self.0.serialize(progress)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
     }
 }
 
+/// Where the results of an optimizer are stored
+pub fn results_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from("./stats");
+    path.push(name);
+    path.set_extension("csv");
+    path
+}
+
+/// The header row that [`ResultsWriter`] writes
+fn results_headers() -> csv::StringRecord {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .serialize(GraphStats::default())
+        .expect("serializing to memory cannot fail");
+    let data = writer.into_inner().expect("flushing to memory cannot fail");
+    csv::Reader::from_reader(data.as_slice())
+        .headers()
+        .expect("header row was just written")
+        .clone()
+}
+
+/// Rewrites a results file from an older version with the current columns.
+/// Otherwise, appending new rows would give us rows with a different number of columns.
+fn migrate_results_file(path: &Path) -> std::io::Result<()> {
+    let Ok(file) = File::open(path) else {
+        return Ok(());
+    };
+    let mut reader = csv::Reader::from_reader(file);
+    if reader.headers()?.is_empty() || *reader.headers()? == results_headers() {
+        return Ok(());
+    }
+    let runs = read_runs(File::open(path)?)?;
+
+    let mut writer = csv::Writer::from_path(path)?;
+    for run in &runs {
+        writer.serialize(run)?;
+    }
+    writer.flush()
+}
+
 pub fn read_all_runs() -> std::io::Result<Vec<RunStats>> {
     let mut all_runs: Vec<RunStats> = vec![];
     for entry in std::fs::read_dir("./stats")? {
         let entry = entry?;
+        // Skips the progress folder
+        if !entry.path().is_file() {
+            continue;
+        }
         let reader = File::open(entry.path())?;
-        all_runs.push(match read_runs(reader) {
-            Ok(runs) => RunStats {
-                name: entry
-                    .path()
-                    .file_stem()
-                    .expect("File name needs to exist")
-                    .to_string_lossy()
-                    .into_owned(),
-                runs,
-            },
+        let runs = match read_runs(reader) {
+            Ok(runs) => runs,
             Err(e) => {
                 panic!("Parsing {:?} failed {}", entry, e);
             }
-        });
+        };
+        let name = entry
+            .path()
+            .file_stem()
+            .expect("File name needs to exist")
+            .to_string_lossy()
+            .into_owned();
+
+        // One file has all versions of an optimizer, but each version is its own entry
+        let first_new = all_runs.len();
+        for run in runs {
+            let label = run_label(&name, &run.version, &run.params);
+            match all_runs[first_new..].iter_mut().find(|v| v.name == label) {
+                Some(version_runs) => version_runs.runs.push(run),
+                None => all_runs.push(RunStats {
+                    name: label,
+                    runs: vec![run],
+                }),
+            }
+        }
     }
     all_runs.sort_by(|a, b| a.name.cmp(&b.name));
     Ok(all_runs)
 }
 
+/// Reads `./stats/progress`, which only exists if an optimizer sent progress
+pub fn read_all_progress() -> std::io::Result<Vec<(String, Vec<ProgressStats>)>> {
+    let mut all_progress = vec![];
+    let Ok(entries) = std::fs::read_dir("./stats/progress") else {
+        return Ok(all_progress);
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_stem()
+            .expect("File name needs to exist")
+            .to_string_lossy()
+            .into_owned();
+        let progress = csv::Reader::from_path(&path)?
+            .deserialize()
+            .collect::<csv::Result<Vec<ProgressStats>>>()
+            .unwrap_or_else(|e| panic!("Parsing {:?} failed {}", path, e));
+        all_progress.push((name, progress));
+    }
+    all_progress.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(all_progress)
+}
+
 /// For analysis, just point a pivot table at the data.
-fn read_runs<R: std::io::Read>(rdr: R) -> csv::Result<Vec<GraphStats>> {
+pub fn read_runs<R: std::io::Read>(rdr: R) -> csv::Result<Vec<GraphStats>> {
     let mut results = vec![];
     let mut rdr = csv::Reader::from_reader(rdr);
+    let has_outcome = rdr.headers()?.iter().any(|v| v == "outcome");
     for result in rdr.deserialize() {
-        let record: GraphStats = result?;
-        results.push(record);
+        let mut run: GraphStats = result?;
+        // The original files only have an empty crossing number for invalid drawings
+        if !has_outcome && run.max_per_edge.is_none() {
+            run.outcome = Outcome::Invalid;
+        }
+        results.push(run);
     }
     Ok(results)
 }
 
 // algorithm,
+
+#[cfg(test)]
+mod test {
+    use super::{GraphStats, Outcome, migrate_results_file, read_runs, results_headers};
+
+    #[test]
+    fn old_results_files_get_outcomes() {
+        let original = "graph,max_per_edge,duration_ms\n/a.json,3,10\n/b.json,,10\n";
+        let runs = read_runs(original.as_bytes()).unwrap();
+        assert_eq!(runs[0].outcome, Outcome::Ok);
+        assert_eq!(runs[1].outcome, Outcome::Invalid);
+
+        let current = "graph,max_per_edge,outcome,reason,duration_ms\n/a.json,,mismatch,edges,10\n";
+        let runs = read_runs(current.as_bytes()).unwrap();
+        assert_eq!(runs[0].outcome, Outcome::Mismatch);
+        assert_eq!(runs[0].reason, "edges");
+    }
+
+    #[test]
+    fn runs_without_result_come_last() {
+        let valid = GraphStats {
+            max_per_edge: Some(7),
+            ..Default::default()
+        };
+        let invalid = GraphStats::default();
+        assert!(valid.compare(&invalid, &[]).is_lt());
+        assert!(invalid.compare(&valid, &[]).is_gt());
+        assert!(invalid.compare(&invalid, &[]).is_eq());
+    }
+
+    #[test]
+    fn old_results_files_get_migrated() {
+        let path =
+            std::env::temp_dir().join(format!("gda_testing_migrate_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "graph,max_per_edge,duration_ms\n/a.json,3,10\n/b.json,,20\n",
+        )
+        .unwrap();
+        migrate_results_file(&path).unwrap();
+        let migrated = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut reader = csv::Reader::from_reader(migrated.as_bytes());
+        assert_eq!(*reader.headers().unwrap(), results_headers());
+        let runs = read_runs(migrated.as_bytes()).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(
+            (runs[0].max_per_edge, runs[0].outcome),
+            (Some(3), Outcome::Ok)
+        );
+        assert_eq!(
+            (runs[1].max_per_edge, runs[1].outcome),
+            (None, Outcome::Invalid)
+        );
+        assert_eq!(runs[1].duration_ms, 20);
+    }
+}
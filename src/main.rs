@@ -1,16 +1,23 @@
 use clap::Parser;
 use cli::Cli;
-use comparer::compare_mode;
+use comparer::{compare_mode, tournament};
 use graphs_runner::GraphsModeRunner;
-use leaderboard::{plots::plot_leaderboard, stats::read_all_runs};
+use leaderboard::{
+    plots::{plot_convergence, plot_leaderboard},
+    stats::{read_all_progress, read_all_runs},
+};
 use smol::{channel, future, io};
+use std::time::Duration;
 
 pub mod cli;
 pub mod comparer;
+pub mod formats;
+pub mod generator;
 pub mod graph;
 pub mod graphs_runner;
 pub mod leaderboard;
 pub mod optimizer_protocol;
+pub mod render;
 
 // For faster compile times, we could
 // - Use the Clap builder API
@@ -29,11 +36,28 @@ fn main() -> io::Result<()> {
             },
             compare_mode::compare_mode(compare_args),
         )),
+        cli::CliCommands::Tournament(tournament_args) => smol::block_on(future::or(
+            async move {
+                is_interrupted.await;
+                io::Result::Ok(())
+            },
+            tournament::tournament(tournament_args),
+        )),
         cli::CliCommands::Graphs {
             optimizer,
             filter,
             skip_to,
             save,
+            isomorphism,
+            explain,
+            timeout,
+            grace,
+            time_limit_ms,
+            seed,
+            jobs,
+            resume,
+            retry_failed,
+            repeat,
         } => smol::block_on(future::or(
             async move {
                 is_interrupted.await;
@@ -44,17 +68,38 @@ fn main() -> io::Result<()> {
                     command: optimizer,
                     filter,
                     skip_to,
-                    save
+                    save,
+                    isomorphism,
+                    explain,
+                    timeout: timeout.map(Duration::from_secs_f64),
+                    grace: Duration::from_secs_f64(grace),
+                    time_limit_ms,
+                    seed,
+                    jobs,
+                    resume,
+                    retry_failed,
+                    repeat,
                 }
                 .run()
                 .await?;
                 Ok(())
             },
         )),
-        cli::CliCommands::Leaderboard {} => {
-            plot_leaderboard(read_all_runs()?)?;
+        cli::CliCommands::Leaderboard { tie_breaker } => {
+            plot_leaderboard(read_all_runs()?, &tie_breaker)?;
+            let all_progress = read_all_progress()?;
+            if !all_progress.is_empty() {
+                plot_convergence(&all_progress)?;
+            }
             Ok(())
         }
+        cli::CliCommands::Best {} => leaderboard::best::print_records(&read_all_runs()?),
+        cli::CliCommands::Convert {
+            files,
+            into,
+            format,
+        } => formats::convert(&files, &into, format),
+        cli::CliCommands::Render { graph, output } => render::render(&graph, output.as_deref()),
         cli::CliCommands::Adversary {} => {
             println!("Not yet implemented");
             Ok(())
@@ -70,7 +115,7 @@ fn get_ctrl_c() -> impl Future<Output = ()> {
     ctrlc::set_handler(handle).unwrap();
 
     async move {
-        while !ctrl_c.recv().await.is_ok() {
+        while ctrl_c.recv().await.is_err() {
             // Wait
         }
     }
@@ -1,7 +1,12 @@
-use std::process::{ExitStatus, Stdio};
+use std::{
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
 
 use clap::builder::styling::{self, Style};
+use serde::{Deserialize, Serialize};
 use smol::{
+    Timer, future,
     io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
     stream::StreamExt,
@@ -17,6 +22,79 @@ pub struct Optimizer {
     process: Child,
     stdin: ChildStdin,
     stdout: BufReader<ChildStdout>,
+    /// What the optimizer announced in its `START` line
+    capabilities: Vec<String>,
+    /// When the last graph was sent, for timestamping the progress
+    graph_sent_at: Instant,
+    /// `PROGRESS` graphs since the last graph was sent
+    progress: Vec<ProgressGraph>,
+}
+
+/// An intermediate solution, and how long the optimizer took to find it
+#[derive(Debug)]
+pub struct ProgressGraph {
+    pub elapsed: Duration,
+    pub graph: Graph,
+}
+
+/// What an optimizer announces in its `START` line.
+/// Either `START {"name":..,"version":..,"params":{..},"capabilities":[..]}`, or `START name +capability`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct OptimizerInfo {
+    pub name: String,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub version: String,
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl OptimizerInfo {
+    /// The name with the version and the parameters, for printing
+    pub fn label(&self) -> String {
+        let params = if self.params.is_empty() {
+            String::new()
+        } else {
+            serde_json::Value::Object(self.params.clone()).to_string()
+        };
+        run_label(&self.name, &self.version, &params)
+    }
+}
+
+/// The name with the version and the parameters, if the optimizer announced them
+pub fn run_label(name: &str, version: &str, params: &str) -> String {
+    [name, version, params]
+        .into_iter()
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Versions like `4` or `"4.1-rc"`
+fn string_or_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(text) => Ok(text),
+        serde_json::Value::Number(number) => Ok(number.to_string()),
+        serde_json::Value::Null => Ok(String::new()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a string or a number, but got {other}"
+        ))),
+    }
+}
+
+/// Optimizers that announce this capability get a `PARAMS` line before every graph
+pub const PARAMS_CAPABILITY: &str = "params";
+
+/// Settings for a single graph, sent as `PARAMS {json}`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GraphParams {
+    /// How long the optimizer should take
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_limit_ms: Option<u64>,
+    /// Seed for randomized optimizers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 pub static LOG_INFO: Style = Style::new().dimmed();
@@ -31,14 +109,14 @@ impl Optimizer {
         #[cfg(target_os = "windows")] // For Windows with its backslashes
         let command = winsplit::split(&command);
         #[cfg(not(target_os = "windows"))] // For sane OSes
-        let command = shlex::split(&command).unwrap();
+        let command = shlex::split(command).unwrap();
 
         Self::from_command(id, command)
     }
 
     fn from_command(id: u32, command: Vec<String>) -> Self {
         let mut process = Command::new(&command[0])
-            .args(command[1..].iter().map(|v| std::ffi::OsStr::new(v)))
+            .args(command[1..].iter().map(std::ffi::OsStr::new))
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -54,6 +132,9 @@ impl Optimizer {
             process,
             stdin,
             stdout,
+            capabilities: vec![],
+            graph_sent_at: Instant::now(),
+            progress: vec![],
         }
     }
 
@@ -77,6 +158,10 @@ impl Optimizer {
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
     /// Redirects stderr to this process's stdout
     pub fn take_stderr(&mut self) -> ChildStderr {
         self.process
@@ -85,7 +170,6 @@ impl Optimizer {
             .expect("failed to get child stderr")
     }
 
-    #[must_use]
     pub fn redirect_stderr(&mut self) -> impl Future<Output = io::Result<()>> + Send + use<> {
         let mut lines = BufReader::new(self.take_stderr()).lines();
         let id = self.id;
@@ -97,33 +181,46 @@ impl Optimizer {
         }
     }
 
+    /// Writes the `PARAMS` line, if the optimizer asked for it.
+    /// Returns whether it was sent.
+    pub async fn write_params(&mut self, params: &GraphParams) -> io::Result<bool> {
+        if !self.has_capability(PARAMS_CAPABILITY) {
+            return Ok(false);
+        }
+        self.stdin
+            .write_all(params_line(params)?.as_bytes())
+            .await?;
+        Ok(true)
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|v| v == capability)
+    }
+
     /// Writes a graph to the child
-    #[must_use]
     pub fn write_graph(&mut self, graph: &Graph) -> impl Future<Output = io::Result<()>> {
         let graph_bytes = serde_json::to_vec(graph).unwrap();
         async move { self.write_graph_bytes(&graph_bytes).await }
     }
 
     /// Writes a graph to the child
-    #[must_use]
-    pub fn write_graph_bytes(&mut self, graph: &[u8]) -> impl Future<Output = io::Result<()>> {
-        async move {
-            self.stdin.write_all(&graph).await?;
-            self.stdin.write_all(b"\n").await?;
-            self.stdin.flush().await?;
-            Ok(())
-        }
+    pub async fn write_graph_bytes(&mut self, graph: &[u8]) -> io::Result<()> {
+        self.stdin.write_all(graph).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+        self.graph_sent_at = Instant::now();
+        self.progress.clear();
+        Ok(())
     }
 
     /// Reads a response from the optimizer
-    #[must_use]
     pub fn read_response(&mut self) -> impl Future<Output = io::Result<OptimizerResponse>> {
         let id = self.id;
         async move {
             loop {
                 let mut line = String::new();
                 self.stdout.read_line(&mut line).await?;
-                if line.len() == 0 {
+                if line.is_empty() {
                     let status = self.process.try_status();
                     if !matches!(status, Ok(None)) {
                         return Ok(OptimizerResponse::NoResponse(status.ok().flatten()));
@@ -132,19 +229,29 @@ impl Optimizer {
                 }
 
                 if let Some(rest) = starts_with(&line, "START") {
-                    return Ok(OptimizerResponse::Start {
-                        name: rest.trim_ascii().to_string(),
-                    });
+                    return Ok(OptimizerResponse::Start(parse_start(rest)?));
                 } else if line.starts_with("GRAPH") {
                     return Ok(OptimizerResponse::GraphRequest);
+                } else if let Some(rest) = starts_with(&line, "PROGRESS") {
+                    match serde_json::from_str(rest) {
+                        Ok(graph) => self.progress.push(ProgressGraph {
+                            elapsed: self.graph_sent_at.elapsed(),
+                            graph,
+                        }),
+                        Err(e) => eprintln!(
+                            "{LOG_WARN}[Optimizer {}] Ignoring progress that failed to parse: {}{LOG_WARN:#}",
+                            id, e
+                        ),
+                    }
                 } else if line.starts_with("{") {
-                    let graph = match serde_json::from_str(&line) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            panic!("Failed to parse {:?} because of {}", line, e);
-                        }
-                    };
-                    return Ok(OptimizerResponse::Graph { graph });
+                    let progress = std::mem::take(&mut self.progress);
+                    return Ok(match serde_json::from_str(&line) {
+                        Ok(graph) => OptimizerResponse::Graph { graph, progress },
+                        Err(e) => OptimizerResponse::Malformed {
+                            line: line.trim_end().to_string(),
+                            error: e.to_string(),
+                        },
+                    });
                 } else {
                     // Optimizers shouldn't print to stdout, but whatever
                     eprintln!(
@@ -156,60 +263,123 @@ impl Optimizer {
         }
     }
 
-    #[must_use]
-    pub fn read_start(&mut self) -> impl Future<Output = io::Result<String>> {
-        async {
-            match self.read_response().await? {
-                OptimizerResponse::Start { name } => Ok(name),
-                response => Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("expected start, but got {:?}", response),
-                )),
+    /// Like [`Self::read_response`], but gives up after the timeout
+    pub async fn read_response_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<OptimizerResponse>> {
+        match timeout {
+            Some(timeout) => {
+                future::or(async { self.read_response().await.map(Some) }, async {
+                    Timer::after(timeout).await;
+                    Ok(None)
+                })
+                .await
             }
+            None => self.read_response().await.map(Some),
         }
     }
 
-    #[must_use]
-    pub fn read_graph(&mut self) -> impl Future<Output = io::Result<Graph>> {
-        async {
-            match self.read_response().await? {
-                OptimizerResponse::Graph { graph } => Ok(graph),
-                response => Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("expected graph, but got {:?}", response),
-                )),
+    /// Replaces a crashed or hanging optimizer with a fresh one, and waits for its `START`
+    pub async fn relaunch(&mut self) -> io::Result<OptimizerInfo> {
+        self.restart().await?;
+        smol::spawn(self.redirect_stderr()).detach();
+        self.read_start().await
+    }
+
+    /// Reads the name, version, parameters and capabilities of the optimizer
+    pub async fn read_start(&mut self) -> io::Result<OptimizerInfo> {
+        match self.read_response().await? {
+            OptimizerResponse::Start(info) => {
+                self.capabilities = info.capabilities.clone();
+                Ok(info)
             }
+            response => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected start, but got {:?}", response),
+            )),
         }
     }
 
-    #[must_use]
-    pub fn read_graph_request(&mut self) -> impl Future<Output = io::Result<()>> {
-        async {
-            match self.read_response().await? {
-                OptimizerResponse::GraphRequest => Ok(()),
-                response => Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("expected graph request, but got {:?}", response),
-                )),
-            }
+    /// Reads a graph. Returns the parse error if the optimizer sent malformed JSON.
+    pub async fn read_graph(&mut self) -> io::Result<Result<Graph, String>> {
+        match self.read_response().await? {
+            OptimizerResponse::Graph { graph, .. } => Ok(Ok(graph)),
+            OptimizerResponse::Malformed { error, .. } => Ok(Err(error)),
+            response => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected graph, but got {:?}", response),
+            )),
         }
     }
+
+    pub async fn read_graph_request(&mut self) -> io::Result<()> {
+        match self.read_response().await? {
+            OptimizerResponse::GraphRequest => Ok(()),
+            response => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected graph request, but got {:?}", response),
+            )),
+        }
+    }
+}
+
+/// The `PARAMS` line, without the settings that aren't set
+fn params_line(params: &GraphParams) -> io::Result<String> {
+    Ok(format!("PARAMS {}\n", serde_json::to_string(params)?))
 }
 
 /// Checks if text starts with a pattern, and returns the remaining text
 fn starts_with<'a>(text: &'a str, pattern: &str) -> Option<&'a str> {
-    if text.starts_with(pattern) {
-        Some(&text[pattern.len()..])
-    } else {
-        None
+    text.strip_prefix(pattern)
+}
+
+/// Parses the JSON form, or splits `START Team1-v4 +params` into the name and the trailing capabilities
+fn parse_start(rest: &str) -> io::Result<OptimizerInfo> {
+    let rest = rest.trim_ascii();
+    if rest.starts_with('{') {
+        let info: OptimizerInfo = serde_json::from_str(rest)?;
+        if info.name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("START is missing a name: {rest}"),
+            ));
+        }
+        return Ok(info);
+    }
+
+    // Only the trailing `+capability` words are taken out, the name stays exactly as it was
+    let mut name = rest;
+    let mut capabilities = vec![];
+    while let Some((head, word)) = name.rsplit_once(|c: char| c.is_ascii_whitespace())
+        && let Some(capability) = word.strip_prefix('+')
+        && !capability.is_empty()
+    {
+        capabilities.push(capability.to_string());
+        name = head.trim_ascii_end();
     }
+    capabilities.reverse();
+    Ok(OptimizerInfo {
+        name: name.to_string(),
+        capabilities,
+        ..Default::default()
+    })
 }
 
 #[derive(Debug)]
 pub enum OptimizerResponse {
-    Start { name: String },
+    Start(OptimizerInfo),
     GraphRequest,
-    Graph { graph: Graph },
+    /// The final graph, and the `PROGRESS` graphs that came before it
+    Graph {
+        graph: Graph,
+        progress: Vec<ProgressGraph>,
+    },
+    /// A line that looked like a graph, but wasn't one
+    Malformed {
+        line: String,
+        error: String,
+    },
     NoResponse(Option<ExitStatus>),
 }
 
@@ -233,3 +403,151 @@ impl<T, E> AllOk<T, E> for (Result<T, E>, Result<T, E>) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        GraphParams, Optimizer, OptimizerResponse, PARAMS_CAPABILITY, params_line, parse_start,
+    };
+
+    #[test]
+    fn start_line_forms() {
+        let plain = parse_start(" Team1-v4-spring-only +params\n").unwrap();
+        assert_eq!(plain.name, "Team1-v4-spring-only");
+        assert_eq!(plain.version, "");
+        assert_eq!(plain.capabilities, vec!["params"]);
+        assert_eq!(plain.label(), "Team1-v4-spring-only");
+
+        // Names from before the capabilities existed stay the same
+        let spaced = parse_start(" Team  1 +x v4 +params +more\n").unwrap();
+        assert_eq!(spaced.name, "Team  1 +x v4");
+        assert_eq!(spaced.capabilities, vec!["params", "more"]);
+        assert_eq!(parse_start(" +params").unwrap().name, "+params");
+
+        let json = parse_start(
+            r#" {"name":"Team1","version":"4","params":{"springs":true},"capabilities":["params"]}"#,
+        )
+        .unwrap();
+        assert_eq!(json.name, "Team1");
+        assert_eq!(json.version, "4");
+        assert_eq!(json.capabilities, vec!["params"]);
+        assert_eq!(json.label(), r#"Team1 4 {"springs":true}"#);
+
+        assert!(parse_start(r#" {"version":"4"}"#).is_err());
+        assert_eq!(
+            parse_start(r#" {"name":"Team1","version":4.5}"#)
+                .unwrap()
+                .version,
+            "4.5"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hanging_optimizers_time_out_and_get_relaunched() {
+        smol::block_on(async {
+            // Sleeps instead of answering, and quits once its stdin is closed
+            let mut optimizer = Optimizer::new(
+                "sh -c 'echo START sleepy; echo GRAPH; read line && sleep 10'",
+                1,
+            );
+            assert_eq!(optimizer.read_start().await.unwrap().name, "sleepy");
+            optimizer.read_graph_request().await.unwrap();
+            optimizer.write_graph_bytes(b"{}").await.unwrap();
+
+            let start = Instant::now();
+            let response = optimizer
+                .read_response_timeout(Some(Duration::from_millis(200)))
+                .await
+                .unwrap();
+            assert!(response.is_none());
+            assert!(start.elapsed() < Duration::from_secs(5));
+
+            // The new process starts over with its START line
+            assert_eq!(optimizer.relaunch().await.unwrap().name, "sleepy");
+            optimizer.read_graph_request().await.unwrap();
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crashes_are_no_response() {
+        smol::block_on(async {
+            let mut optimizer = Optimizer::new("sh -c 'echo START crash; echo GRAPH; exit 3'", 1);
+            optimizer.read_start().await.unwrap();
+            optimizer.read_graph_request().await.unwrap();
+            let response = optimizer.read_response_timeout(None).await.unwrap();
+            match response {
+                Some(OptimizerResponse::NoResponse(Some(status))) => {
+                    assert_eq!(status.code(), Some(3))
+                }
+                other => panic!("expected a crash, but got {other:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn params_lines() {
+        let params = GraphParams {
+            time_limit_ms: Some(1500),
+            seed: Some(7),
+        };
+        assert_eq!(
+            params_line(&params).unwrap(),
+            "PARAMS {\"time_limit_ms\":1500,\"seed\":7}\n"
+        );
+        let params = GraphParams {
+            seed: Some(7),
+            ..Default::default()
+        };
+        assert_eq!(params_line(&params).unwrap(), "PARAMS {\"seed\":7}\n");
+        assert_eq!(params_line(&GraphParams::default()).unwrap(), "PARAMS {}\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_optimizers_that_ask_get_params() {
+        smol::block_on(async {
+            for (start, expected) in [("START p +params", true), ("START p", false)] {
+                let mut optimizer = Optimizer::new(&format!("sh -c 'echo {start}; read line'"), 1);
+                optimizer.read_start().await.unwrap();
+                assert_eq!(optimizer.has_capability(PARAMS_CAPABILITY), expected);
+                let sent = optimizer.write_params(&GraphParams::default()).await;
+                assert_eq!(sent.unwrap(), expected);
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn progress_comes_with_the_final_graph() {
+        smol::block_on(async {
+            // Sends the graph back as progress, then something broken, then as the final graph
+            let mut optimizer = Optimizer::new(
+                r#"sh -c 'echo START p; echo GRAPH; read line; echo "PROGRESS $line"; echo "PROGRESS {broken"; echo "$line"; read line'"#,
+                1,
+            );
+            optimizer.read_start().await.unwrap();
+            optimizer.read_graph_request().await.unwrap();
+            optimizer
+                .write_graph_bytes(br#"{"nodes":[{"id":0,"x":1,"y":2}],"edges":[]}"#)
+                .await
+                .unwrap();
+            match optimizer.read_response().await.unwrap() {
+                OptimizerResponse::Graph { graph, progress } => {
+                    assert_eq!(graph.nodes.len(), 1);
+                    // The broken progress line is skipped
+                    assert_eq!(progress.len(), 1);
+                    assert_eq!(
+                        (progress[0].graph.nodes[0].x, progress[0].graph.nodes[0].y),
+                        (1, 2)
+                    );
+                    assert!(progress[0].elapsed < Duration::from_secs(5));
+                }
+                other => panic!("expected a graph, but got {other:?}"),
+            }
+        });
+    }
+}
@@ -0,0 +1,182 @@
+//! Draws a graph as an SVG, for taking a closer look at suspicious solutions.
+
+use std::{fmt::Write, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::graph::{Graph, GraphViolation};
+
+/// Size of the longer side of the image, in pixels
+const IMAGE_SIZE: f64 = 1000.0;
+const VIOLATION_COLOR: &str = "#e600e6";
+
+/// A graph, or a record from `./best` that contains one
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GraphFile {
+    Graph(Graph),
+    Record { graph: Graph },
+}
+
+/// Reads a graph from a JSON file, and writes the SVG next to it or to `output`
+pub fn render(input: &Path, output: Option<&Path>) -> io::Result<()> {
+    let graph = match serde_json::from_slice(&std::fs::read(input)?)? {
+        GraphFile::Graph(graph) => graph,
+        GraphFile::Record { graph } => graph,
+    };
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| input.with_extension("svg"));
+    std::fs::write(&output, render_svg(&graph))?;
+    println!("Rendered {} to {}", input.display(), output.display());
+    Ok(())
+}
+
+/// Colors edges from blue (no crossings) to red (`max_per_edge` crossings),
+/// draws the edges at the maximum thicker, and marks the validation violations
+pub fn render_svg(graph: &Graph) -> String {
+    let has_valid_edges = graph
+        .edges
+        .iter()
+        .all(|e| e.source < graph.nodes.len() && e.target < graph.nodes.len());
+    let (per_edge, max_per_edge) = if has_valid_edges {
+        let report = graph.crossing_report();
+        (report.per_edge, report.max_per_edge)
+    } else {
+        (vec![0; graph.edges.len()], 0)
+    };
+    let violations = graph
+        .is_valid()
+        .err()
+        .map(|e| e.violations)
+        .unwrap_or_default();
+
+    let width = graph.width.max(1) as f64;
+    let height = graph.height.max(1) as f64;
+    let scale = IMAGE_SIZE / width.max(height);
+    let padding = 0.02 * width.max(height);
+    let node_radius = 0.004 * width.max(height);
+
+    let mut svg = String::new();
+    _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="{} {} {} {}">"#,
+        (width + 2.0 * padding) * scale,
+        (height + 2.0 * padding) * scale,
+        -padding,
+        -padding,
+        width + 2.0 * padding,
+        height + 2.0 * padding,
+    );
+    _ = writeln!(
+        svg,
+        "<title>max per edge {max_per_edge}, {} violations</title>",
+        violations.len()
+    );
+    // SVG coordinates grow downwards, so flip the y axis to match the plots
+    _ = writeln!(svg, r#"<g transform="translate(0 {height}) scale(1 -1)">"#);
+    _ = writeln!(
+        svg,
+        r##"<rect x="0" y="0" width="{width}" height="{height}" fill="none" stroke="#ccc" vector-effect="non-scaling-stroke"/>"##
+    );
+
+    for point in &graph.points {
+        _ = writeln!(
+            svg,
+            r##"<rect x="{}" y="{}" width="{r}" height="{r}" fill="#bbb"/>"##,
+            point.x as f64 - node_radius / 2.0,
+            point.y as f64 - node_radius / 2.0,
+            r = node_radius,
+        );
+    }
+
+    for (index, edge) in graph.edges.iter().enumerate() {
+        let (Some(source), Some(target)) =
+            (graph.nodes.get(edge.source), graph.nodes.get(edge.target))
+        else {
+            continue;
+        };
+        let crossings = per_edge[index];
+        let is_worst = max_per_edge > 0 && crossings == max_per_edge;
+        let hue = if max_per_edge == 0 {
+            220.0
+        } else {
+            220.0 * (1.0 - crossings as f64 / max_per_edge as f64)
+        };
+        _ = writeln!(
+            svg,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="hsl({hue:.0},80%,45%)" stroke-width="{}" stroke-opacity="{}" vector-effect="non-scaling-stroke"><title>edge {index} ({} - {}): {crossings} crossings</title></line>"#,
+            source.x,
+            source.y,
+            target.x,
+            target.y,
+            if is_worst { 3 } else { 1 },
+            if is_worst { 1.0 } else { 0.6 },
+            edge.source,
+            edge.target,
+        );
+    }
+
+    for node in &graph.nodes {
+        _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{node_radius}" fill="black"><title>node {} ({}, {})</title></circle>"#,
+            node.x, node.y, node.id, node.x, node.y
+        );
+    }
+
+    for violation in &violations {
+        let description = violation.to_string();
+        match violation {
+            GraphViolation::OverlappingEdges { from, to, .. } => {
+                _ = writeln!(
+                    svg,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{VIOLATION_COLOR}" stroke-width="5" stroke-dasharray="8 4" vector-effect="non-scaling-stroke"><title>{description}</title></line>"#,
+                    from.0, from.1, to.0, to.1
+                );
+            }
+            GraphViolation::OutOfBounds { x, y, .. }
+            | GraphViolation::OverlappingNodes { x, y, .. }
+            | GraphViolation::NodeOnEdge { x, y, .. }
+            | GraphViolation::NodeNotOnPoint { x, y, .. } => {
+                _ = writeln!(
+                    svg,
+                    r#"<circle cx="{x}" cy="{y}" r="{}" fill="none" stroke="{VIOLATION_COLOR}" stroke-width="3" vector-effect="non-scaling-stroke"><title>{description}</title></circle>"#,
+                    node_radius * 3.0
+                );
+            }
+            // These don't have a position
+            GraphViolation::NodeIdOutOfBounds { .. }
+            | GraphViolation::DuplicateNodeId { .. }
+            | GraphViolation::MissingNodeId { .. }
+            | GraphViolation::EdgeEndpointOutOfBounds { .. } => {}
+        }
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod test {
+    use super::render_svg;
+    use crate::graph::Graph;
+
+    #[test]
+    fn draws_every_node_and_edge() {
+        let graph: Graph = serde_json::from_str(
+            r#"{"nodes":[{"id":0,"x":0,"y":0},{"id":1,"x":10,"y":10},{"id":2,"x":0,"y":10},{"id":3,"x":10,"y":0}],
+                "edges":[{"source":0,"target":1},{"source":2,"target":3},{"source":0,"target":2}],
+                "width":10,"height":10}"#,
+        )
+        .unwrap();
+        let svg = render_svg(&graph);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains(r#"viewBox="-0.2 -0.2 10.4 10.4""#));
+        assert!(svg.contains(r#"transform="translate(0 10) scale(1 -1)""#));
+        assert_eq!(svg.matches("<title>node ").count(), 4);
+        assert_eq!(svg.matches("<title>edge ").count(), 3);
+        assert!(svg.contains("<title>max per edge 1, 0 violations</title>"));
+    }
+}